@@ -0,0 +1,139 @@
+use crate::*;
+use near_sdk::{
+    borsh::{BorshDeserialize, BorshSerialize},
+    near_bindgen,
+    serde::{Deserialize, Serialize},
+    AccountId,
+};
+
+// Aggregate sales counters, kept per series, per creator and for the whole contract
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct SalesStats {
+    pub units_sold: u64,
+    pub resales: u64,
+    pub renewals: u64,
+    pub upgrades: u64,
+    pub primary_volume: Balance,
+    pub secondary_volume: Balance,
+    pub renewal_volume: Balance,
+    pub upgrade_volume: Balance,
+    pub platform_fees: Balance,
+    pub venue_fees: Balance,
+    pub royalties_paid: Balance,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SalesStatsJson {
+    pub units_sold: U64,
+    pub renewals: U64,
+    pub upgrades: U64,
+    // Sales paid through the contract
+    pub gross_volume: U128,
+    pub primary_volume: U128,
+    pub renewal_volume: U128,
+    pub upgrade_volume: U128,
+    pub platform_fees: U128,
+    pub venue_fees: U128,
+    pub royalties_paid: U128,
+    // Resales as reported by the marketplaces calling nft_transfer_payout
+    pub self_reported_resales: U64,
+    pub self_reported_secondary_volume: U128,
+}
+
+impl SalesStats {
+    fn record(&mut self, receipt: &SaleReceipt) {
+        let price = receipt.price.0;
+        match receipt.sale_type {
+            SaleType::Primary => {
+                self.units_sold = self.units_sold.saturating_add(1);
+                self.primary_volume = self.primary_volume.saturating_add(price);
+            }
+            SaleType::Secondary => {
+                // The contract never sees the funds, so nothing else is counted
+                self.resales = self.resales.saturating_add(1);
+                self.secondary_volume = self.secondary_volume.saturating_add(price);
+                return;
+            }
+            SaleType::Renewal => {
+                self.renewals = self.renewals.saturating_add(1);
+                self.renewal_volume = self.renewal_volume.saturating_add(price);
+            }
+            SaleType::Upgrade => {
+                self.upgrades = self.upgrades.saturating_add(1);
+                self.upgrade_volume = self.upgrade_volume.saturating_add(price);
+            }
+        }
+        self.platform_fees = self.platform_fees.saturating_add(receipt.platform_fee.0);
+        self.venue_fees = self.venue_fees.saturating_add(receipt.venue_fee.0);
+        self.royalties_paid = receipt
+            .royalties
+            .values()
+            .fold(self.royalties_paid, |total, amount| {
+                total.saturating_add(amount.0)
+            });
+    }
+
+    fn to_json(&self) -> SalesStatsJson {
+        SalesStatsJson {
+            units_sold: self.units_sold.into(),
+            renewals: self.renewals.into(),
+            upgrades: self.upgrades.into(),
+            gross_volume: self
+                .primary_volume
+                .saturating_add(self.renewal_volume)
+                .saturating_add(self.upgrade_volume)
+                .into(),
+            primary_volume: self.primary_volume.into(),
+            renewal_volume: self.renewal_volume.into(),
+            upgrade_volume: self.upgrade_volume.into(),
+            platform_fees: self.platform_fees.into(),
+            venue_fees: self.venue_fees.into(),
+            royalties_paid: self.royalties_paid.into(),
+            self_reported_resales: self.resales.into(),
+            self_reported_secondary_volume: self.secondary_volume.into(),
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_sales_stats(&self) -> SalesStatsJson {
+        self.sales_stats.to_json()
+    }
+
+    pub fn get_series_sales_stats(&self, token_series_id: TokenSeriesId) -> SalesStatsJson {
+        self.series_sales_stats
+            .get(&token_series_id)
+            .unwrap_or_default()
+            .to_json()
+    }
+
+    pub fn get_creator_sales_stats(&self, creator_id: AccountId) -> SalesStatsJson {
+        self.creator_sales_stats
+            .get(&creator_id)
+            .unwrap_or_default()
+            .to_json()
+    }
+
+    pub(crate) fn internal_record_sale_stats(&mut self, receipt: &SaleReceipt) {
+        self.sales_stats.record(receipt);
+
+        let mut series_stats = self
+            .series_sales_stats
+            .get(&receipt.token_series_id)
+            .unwrap_or_default();
+        series_stats.record(receipt);
+        self.series_sales_stats
+            .insert(&receipt.token_series_id, &series_stats);
+
+        let mut creator_stats = self
+            .creator_sales_stats
+            .get(&receipt.creator_id)
+            .unwrap_or_default();
+        creator_stats.record(receipt);
+        self.creator_sales_stats
+            .insert(&receipt.creator_id, &creator_stats);
+    }
+}
@@ -0,0 +1,233 @@
+use crate::*;
+use near_sdk::{
+    assert_one_yocto,
+    borsh::{BorshDeserialize, BorshSerialize},
+    near_bindgen,
+    serde::{Deserialize, Serialize},
+    AccountId,
+};
+
+pub type CapacityPoolId = String;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct CapacityPool {
+    pub owner_id: AccountId,
+    pub capacity: u64,
+    // Tokens minted from every series in the pool
+    pub minted: u64,
+    pub series: UnorderedSet<TokenSeriesId>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CapacityPoolJson {
+    pub capacity_pool_id: CapacityPoolId,
+    pub owner_id: AccountId,
+    pub capacity: U64,
+    pub minted: U64,
+    pub remaining: U64,
+    pub series: Vec<TokenSeriesId>,
+}
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn create_capacity_pool(&mut self, capacity: U64) -> CapacityPoolJson {
+        assert!(
+            self.whitelisted_creators
+                .contains(&env::predecessor_account_id())
+                || env::predecessor_account_id() == self.tokens.owner_id,
+            "Marketplace: Creator must be whitelisted"
+        );
+
+        let initial_storage_usage = env::storage_usage();
+        let capacity_pool_id = format!("{}", (self.capacity_pools.len() + 1));
+
+        let capacity_pool = CapacityPool {
+            owner_id: env::predecessor_account_id(),
+            capacity: capacity.0,
+            minted: 0,
+            series: UnorderedSet::new(StorageKey::CapacityPoolSeriesInner {
+                capacity_pool_id: capacity_pool_id.clone(),
+            }),
+        };
+        self.capacity_pools
+            .insert(&capacity_pool_id, &capacity_pool);
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"capacity_pool_create",
+            "data": {
+                    "capacity_pool_id": capacity_pool_id,
+                    "owner_id": capacity_pool.owner_id,
+                    "capacity": capacity,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+
+        refund_deposit(env::storage_usage() - initial_storage_usage, 0);
+
+        self.get_capacity_pool(capacity_pool_id).unwrap()
+    }
+
+    #[payable]
+    pub fn set_capacity_pool_capacity(&mut self, capacity_pool_id: CapacityPoolId, capacity: U64) {
+        assert_one_yocto();
+
+        let mut capacity_pool = self
+            .capacity_pools
+            .get(&capacity_pool_id)
+            .expect("Marketplace: Capacity pool not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            capacity_pool.owner_id,
+            "Marketplace: Pool owner only"
+        );
+        assert!(
+            capacity.0 >= capacity_pool.minted,
+            "Marketplace: capacity is less than minted tokens"
+        );
+
+        capacity_pool.capacity = capacity.0;
+        self.capacity_pools
+            .insert(&capacity_pool_id, &capacity_pool);
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"capacity_pool_set_capacity",
+            "data": {
+                    "capacity_pool_id": capacity_pool_id,
+                    "capacity": capacity,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+    }
+
+    #[payable]
+    pub fn nft_set_series_capacity_pool(
+        &mut self,
+        token_series_id: TokenSeriesId,
+        capacity_pool_id: Option<CapacityPoolId>,
+    ) {
+        let initial_storage_usage = env::storage_usage();
+
+        let mut token_series = self
+            .token_series_by_id
+            .get(&token_series_id)
+            .expect("Marketplace: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "Marketplace: Creator only"
+        );
+        // Pool counts only hold up if a series joins or leaves before its first mint
+        assert_eq!(
+            token_series.tokens.len(),
+            0,
+            "Marketplace: Token series already has minted tokens"
+        );
+
+        if let Some(previous_pool_id) = &token_series.capacity_pool_id {
+            let mut capacity_pool = self.capacity_pools.get(previous_pool_id).unwrap();
+            capacity_pool.series.remove(&token_series_id);
+            self.capacity_pools.insert(previous_pool_id, &capacity_pool);
+        }
+
+        if let Some(capacity_pool_id) = &capacity_pool_id {
+            let mut capacity_pool = self
+                .capacity_pools
+                .get(capacity_pool_id)
+                .expect("Marketplace: Capacity pool not exist");
+            assert_eq!(
+                env::predecessor_account_id(),
+                capacity_pool.owner_id,
+                "Marketplace: Pool owner only"
+            );
+            capacity_pool.series.insert(&token_series_id);
+            self.capacity_pools.insert(capacity_pool_id, &capacity_pool);
+        }
+
+        token_series.capacity_pool_id = capacity_pool_id.clone();
+        self.token_series_by_id
+            .insert(&token_series_id, &token_series);
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"nft_set_series_capacity_pool",
+            "data": {
+                    "token_series_id": token_series_id,
+                    "capacity_pool_id": capacity_pool_id,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+
+        refund_deposit(
+            env::storage_usage().saturating_sub(initial_storage_usage),
+            0,
+        );
+    }
+
+    pub fn get_capacity_pool(&self, capacity_pool_id: CapacityPoolId) -> Option<CapacityPoolJson> {
+        let capacity_pool = self.capacity_pools.get(&capacity_pool_id)?;
+
+        Some(CapacityPoolJson {
+            capacity_pool_id,
+            owner_id: capacity_pool.owner_id,
+            capacity: capacity_pool.capacity.into(),
+            minted: capacity_pool.minted.into(),
+            remaining: (capacity_pool.capacity - capacity_pool.minted).into(),
+            series: capacity_pool.series.to_vec(),
+        })
+    }
+
+    // None when neither the series copies nor a pool limit the supply
+    pub fn nft_series_remaining_capacity(&self, token_series_id: TokenSeriesId) -> Option<U64> {
+        let token_series = self
+            .token_series_by_id
+            .get(&token_series_id)
+            .expect("Marketplace: Token series not exist");
+
+        let series_remaining = token_series
+            .metadata
+            .copies
+            .map(|copies| copies.saturating_sub(token_series.tokens.len()));
+        let pool_remaining = token_series
+            .capacity_pool_id
+            .and_then(|capacity_pool_id| self.capacity_pools.get(&capacity_pool_id))
+            .map(|capacity_pool| capacity_pool.capacity - capacity_pool.minted);
+
+        match (series_remaining, pool_remaining) {
+            (Some(series_remaining), Some(pool_remaining)) => {
+                Some(series_remaining.min(pool_remaining).into())
+            }
+            (remaining, None) | (None, remaining) => remaining.map(U64),
+        }
+    }
+
+    pub(crate) fn internal_consume_capacity(&mut self, capacity_pool_id: &CapacityPoolId) {
+        let mut capacity_pool = self.capacity_pools.get(capacity_pool_id).unwrap();
+        assert!(
+            capacity_pool.minted < capacity_pool.capacity,
+            "Marketplace: Capacity pool is full"
+        );
+
+        capacity_pool.minted += 1;
+        self.capacity_pools.insert(capacity_pool_id, &capacity_pool);
+    }
+}
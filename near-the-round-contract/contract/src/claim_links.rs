@@ -0,0 +1,421 @@
+use crate::*;
+use near_sdk::{
+    borsh::{BorshDeserialize, BorshSerialize},
+    near_bindgen,
+    serde::{Deserialize, Serialize},
+    AccountId, Allowance, Gas, PromiseResult, PublicKey,
+};
+
+// Gas allowance of the function call access key added for every claim link
+pub const CLAIM_LINK_ALLOWANCE: Balance = 50_000_000_000_000_000_000_000;
+// Part of the claim link deposit reserved for the storage of the minted token
+pub const CLAIM_LINK_MINT_STORAGE: Balance = 20_000_000_000_000_000_000_000;
+// Claims signed with the key buy their gas from the allowance at the minimum gas price
+pub const CLAIM_LINK_GAS_PRICE: Balance = 100_000_000;
+pub const CLAIM_LINK_METHODS: &str = "nft_claim_link,nft_claim_link_new_account";
+
+const GAS_FOR_CREATE_ACCOUNT: Gas = Gas::from_gas(40_000_000_000_000);
+const GAS_FOR_ON_ACCOUNT_CREATED: Gas = Gas::from_gas(30_000_000_000_000);
+const GAS_FOR_ON_CLAIM_LINK_REFUNDED: Gas = Gas::from_gas(10_000_000_000_000);
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub struct ClaimLink {
+    pub token_series_id: TokenSeriesId,
+    pub creator_id: AccountId,
+    pub deposit: U128,
+    // Part of the key allowance not spent on claims yet, owed back to the creator
+    pub allowance: U128,
+    pub expires_at: TimestampSec,
+}
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn nft_create_claim_link(
+        &mut self,
+        token_series_id: TokenSeriesId,
+        public_key: PublicKey,
+        expires_at: TimestampSec,
+    ) -> ClaimLink {
+        let initial_storage_usage = env::storage_usage();
+
+        let token_series = self
+            .token_series_by_id
+            .get(&token_series_id)
+            .expect("Marketplace: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "Marketplace: Creator only"
+        );
+        assert!(
+            expires_at > to_sec(env::block_timestamp()),
+            "Marketplace: expires_at is less than current block_timestamp"
+        );
+        assert!(
+            self.claim_links.get(&public_key).is_none(),
+            "Marketplace: Claim link already exists"
+        );
+        assert!(
+            token_series.is_mintable,
+            "Marketplace: Token series is not mintable"
+        );
+
+        // Every link holds back a copy until it is claimed or reclaimed
+        let reserved = self.internal_claim_link_reservations(&token_series_id);
+        if let Some(copies) = token_series.metadata.copies {
+            assert!(
+                token_series.tokens.len() + reserved < copies,
+                "Marketplace: No copies left to reserve"
+            );
+        }
+        self.claim_link_reservations
+            .insert(&token_series_id, &(reserved + 1));
+
+        let extra_spend = CLAIM_LINK_ALLOWANCE + CLAIM_LINK_MINT_STORAGE;
+        assert!(
+            env::attached_deposit().as_yoctonear() > extra_spend,
+            "Marketplace: attached deposit is less than {}",
+            extra_spend
+        );
+
+        // Placeholder so the slot's own storage is included in the measurement
+        let mut claim_link = ClaimLink {
+            token_series_id: token_series_id.clone(),
+            creator_id: token_series.creator_id,
+            deposit: U128(0),
+            allowance: U128(CLAIM_LINK_ALLOWANCE),
+            expires_at,
+        };
+        self.claim_links.insert(&public_key, &claim_link);
+
+        let storage_cost = env::storage_byte_cost().as_yoctonear()
+            * Balance::from(env::storage_usage() - initial_storage_usage);
+        assert!(
+            env::attached_deposit().as_yoctonear() >= extra_spend + storage_cost,
+            "Must attach {} yoctoNEAR to cover storage",
+            extra_spend + storage_cost,
+        );
+
+        // Everything but the storage and the key allowance funds the claim
+        claim_link.deposit =
+            U128(env::attached_deposit().as_yoctonear() - storage_cost - CLAIM_LINK_ALLOWANCE);
+        self.claim_links.insert(&public_key, &claim_link);
+        self.liabilities.claim_link_deposits += claim_link.deposit.0 + claim_link.allowance.0;
+
+        Promise::new(env::current_account_id()).add_access_key_allowance(
+            public_key.clone(),
+            Allowance::limited(NearToken::from_yoctonear(CLAIM_LINK_ALLOWANCE)).unwrap(),
+            env::current_account_id(),
+            CLAIM_LINK_METHODS.to_string(),
+        );
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"nft_create_claim_link",
+            "data": {
+                    "token_series_id": token_series_id,
+                    "public_key": public_key,
+                    "creator_id": claim_link.creator_id,
+                    "deposit": claim_link.deposit,
+                    "expires_at": expires_at,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+
+        claim_link
+    }
+
+    pub fn nft_claim_link(&mut self, receiver_id: AccountId) -> Token {
+        let public_key = env::signer_account_pk();
+        let initial_storage_usage = env::storage_usage();
+        let claim_link = self.internal_take_claim_link(&public_key);
+        self.internal_release_claim_link_reservation(&claim_link.token_series_id);
+        let freed_storage = initial_storage_usage.saturating_sub(env::storage_usage());
+
+        let token =
+            self.internal_mint_claim_link(&public_key, claim_link, receiver_id, freed_storage);
+
+        Promise::new(env::current_account_id()).delete_key(public_key);
+
+        token
+    }
+
+    pub fn nft_claim_link_new_account(
+        &mut self,
+        new_account_id: AccountId,
+        new_public_key: PublicKey,
+    ) -> Promise {
+        let public_key = env::signer_account_pk();
+        let initial_storage_usage = env::storage_usage();
+        let claim_link = self.internal_take_claim_link(&public_key);
+        let freed_storage = initial_storage_usage - env::storage_usage();
+
+        // The account is only funded if the token can still be minted to it
+        let token_series = self
+            .token_series_by_id
+            .get(&claim_link.token_series_id)
+            .unwrap();
+        let reserved = self.internal_claim_link_reservations(&claim_link.token_series_id);
+        let reason = self.internal_mint_block_reason(&token_series, 1, reserved - 1);
+        assert!(reason.is_none(), "{}", reason.unwrap_or_default());
+
+        // Named accounts are created through the linkdrop contract of their top-level account
+        let linkdrop_id: AccountId = new_account_id
+            .as_str()
+            .rsplit('.')
+            .next()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_ne!(
+            linkdrop_id, new_account_id,
+            "Marketplace: new_account_id must be a named sub-account"
+        );
+
+        Promise::new(linkdrop_id)
+            .function_call(
+                "create_account".to_string(),
+                json!({
+                    "new_account_id": new_account_id,
+                    "new_public_key": new_public_key,
+                })
+                .to_string()
+                .into_bytes(),
+                NearToken::from_yoctonear(claim_link.deposit.0 - CLAIM_LINK_MINT_STORAGE),
+                GAS_FOR_CREATE_ACCOUNT,
+            )
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_ON_ACCOUNT_CREATED)
+                    .on_claim_link_account_created(
+                        public_key,
+                        claim_link,
+                        new_account_id,
+                        freed_storage.into(),
+                    ),
+            )
+    }
+
+    #[private]
+    pub fn on_claim_link_account_created(
+        &mut self,
+        public_key: PublicKey,
+        claim_link: ClaimLink,
+        new_account_id: AccountId,
+        freed_storage: U64,
+    ) -> Option<Token> {
+        let is_created = match env::promise_result(0) {
+            PromiseResult::Successful(value) => {
+                near_sdk::serde_json::from_slice::<bool>(&value).unwrap_or(true)
+            }
+            _ => false,
+        };
+
+        if !is_created {
+            // The linkdrop refunds the account funds in a receipt of its own issued
+            // alongside this callback, so the link only counts as a liability again
+            // from the next receipt on
+            Self::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_ON_CLAIM_LINK_REFUNDED)
+                .on_claim_link_refunded(public_key, claim_link);
+            return None;
+        }
+
+        // The rest of the deposit went to the new account
+        let mut claim_link = claim_link;
+        claim_link.deposit = U128(CLAIM_LINK_MINT_STORAGE);
+        let initial_storage_usage = env::storage_usage();
+        self.internal_release_claim_link_reservation(&claim_link.token_series_id);
+        let freed_storage =
+            freed_storage.0 + initial_storage_usage.saturating_sub(env::storage_usage());
+        Promise::new(env::current_account_id()).delete_key(public_key.clone());
+
+        // The series may have changed while the account was created
+        let token_series = self
+            .token_series_by_id
+            .get(&claim_link.token_series_id)
+            .unwrap();
+        let reserved = self.internal_claim_link_reservations(&claim_link.token_series_id);
+        if let Some(reason) = self.internal_mint_block_reason(&token_series, 1, reserved) {
+            let refund = claim_link.deposit.0
+                + claim_link.allowance.0
+                + env::storage_byte_cost().as_yoctonear() * Balance::from(freed_storage);
+            Promise::new(claim_link.creator_id.clone()).transfer(NearToken::from_yoctonear(refund));
+
+            let log_data = &json!({
+                "standard":"nep171",
+                "version":"1.0.0",
+                "event":"nft_claim_link_failed",
+                "data": {
+                        "token_series_id": claim_link.token_series_id,
+                        "public_key": public_key,
+                        "new_account_id": new_account_id,
+                        "reason": reason,
+                        "refund": U128(refund),
+                }
+            })
+            .to_string();
+
+            let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+            env::log_str(&event);
+
+            return None;
+        }
+
+        Some(self.internal_mint_claim_link(&public_key, claim_link, new_account_id, freed_storage))
+    }
+
+    // Account creation failed and its funds are back, so the link can be claimed again
+    #[private]
+    pub fn on_claim_link_refunded(&mut self, public_key: PublicKey, claim_link: ClaimLink) {
+        self.liabilities.claim_link_deposits += claim_link.deposit.0 + claim_link.allowance.0;
+        self.claim_links.insert(&public_key, &claim_link);
+    }
+
+    #[payable]
+    pub fn nft_reclaim_claim_link(&mut self, public_key: PublicKey) {
+        assert_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+
+        let claim_link = self
+            .claim_links
+            .get(&public_key)
+            .expect("Marketplace: Claim link not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            claim_link.creator_id,
+            "Marketplace: Creator only"
+        );
+        assert!(
+            to_sec(env::block_timestamp()) >= claim_link.expires_at,
+            "Marketplace: Claim link not expired yet"
+        );
+
+        self.claim_links.remove(&public_key);
+        self.internal_release_claim_link_reservation(&claim_link.token_series_id);
+        self.liabilities.claim_link_deposits -= claim_link.deposit.0 + claim_link.allowance.0;
+
+        // Claims that failed with the key are not tracked, the allowance left is refunded
+        let refund = claim_link.deposit.0
+            + claim_link.allowance.0
+            + env::storage_byte_cost().as_yoctonear()
+                * Balance::from(initial_storage_usage.saturating_sub(env::storage_usage()));
+        Promise::new(claim_link.creator_id.clone()).transfer(NearToken::from_yoctonear(refund));
+        Promise::new(env::current_account_id()).delete_key(public_key.clone());
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"nft_reclaim_claim_link",
+            "data": {
+                    "token_series_id": claim_link.token_series_id,
+                    "public_key": public_key,
+                    "creator_id": claim_link.creator_id,
+                    "refund": U128(refund),
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+    }
+
+    pub fn nft_get_claim_link(&self, public_key: PublicKey) -> Option<ClaimLink> {
+        self.claim_links.get(&public_key)
+    }
+
+    pub(crate) fn internal_claim_link_reservations(&self, token_series_id: &TokenSeriesId) -> u64 {
+        self.claim_link_reservations
+            .get(token_series_id)
+            .unwrap_or(0)
+    }
+
+    fn internal_release_claim_link_reservation(&mut self, token_series_id: &TokenSeriesId) {
+        let reserved = self.internal_claim_link_reservations(token_series_id);
+        if reserved > 1 {
+            self.claim_link_reservations
+                .insert(token_series_id, &(reserved - 1));
+        } else {
+            self.claim_link_reservations.remove(token_series_id);
+        }
+    }
+
+    fn internal_take_claim_link(&mut self, public_key: &PublicKey) -> ClaimLink {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "Marketplace: Claim must be signed with the claim link key"
+        );
+
+        let mut claim_link = self
+            .claim_links
+            .remove(public_key)
+            .expect("Marketplace: Claim link not exist");
+        assert!(
+            to_sec(env::block_timestamp()) < claim_link.expires_at,
+            "Marketplace: Claim link expired"
+        );
+        self.liabilities.claim_link_deposits -= claim_link.deposit.0 + claim_link.allowance.0;
+
+        // This transaction is paid from the allowance, including any callbacks
+        let gas_cost = Balance::from(env::prepaid_gas().as_gas()) * CLAIM_LINK_GAS_PRICE;
+        claim_link.allowance = U128(claim_link.allowance.0.saturating_sub(gas_cost));
+
+        claim_link
+    }
+
+    fn internal_mint_claim_link(
+        &mut self,
+        public_key: &PublicKey,
+        claim_link: ClaimLink,
+        receiver_id: AccountId,
+        freed_storage: u64,
+    ) -> Token {
+        let initial_storage_usage = env::storage_usage();
+        let token = self._nft_mint_series(claim_link.token_series_id.clone(), receiver_id);
+        let storage_cost = env::storage_byte_cost().as_yoctonear()
+            * Balance::from(env::storage_usage() - initial_storage_usage);
+
+        assert!(
+            claim_link.deposit.0 >= storage_cost,
+            "Marketplace: Claim link deposit doesn't cover storage"
+        );
+
+        // Unused deposit and allowance and the freed slot storage go back to the creator
+        let refund = claim_link.deposit.0 - storage_cost
+            + claim_link.allowance.0
+            + env::storage_byte_cost().as_yoctonear() * Balance::from(freed_storage);
+        if refund > 1 {
+            Promise::new(claim_link.creator_id.clone()).transfer(NearToken::from_yoctonear(refund));
+        }
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"nft_claim_link",
+            "data": {
+                    "token_series_id": claim_link.token_series_id,
+                    "public_key": public_key,
+                    "token_id": token.token_id,
+                    "owner_id": token.owner_id,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+
+        token
+    }
+}
@@ -0,0 +1,272 @@
+use crate::*;
+use near_sdk::{
+    borsh::{BorshDeserialize, BorshSerialize},
+    collections::Vector,
+    json_types::Base64VecU8,
+    near_bindgen,
+    serde::{Deserialize, Serialize},
+    AccountId, CryptoHash, PublicKey,
+};
+
+// Codes are committed as sha256(salt || code), one hash per slot
+#[derive(BorshDeserialize, BorshSerialize)]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct SeriesCodes {
+    code_hashes: Vector<CryptoHash>,
+    claimed: u64,
+}
+
+// Only the hash is kept on-chain, the creator delivers salt and code encrypted
+// to the key the holder claimed with
+#[derive(BorshDeserialize, BorshSerialize)]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct CodeClaim {
+    slot: u64,
+    claimed_by: AccountId,
+    claimed_at: TimestampSec,
+    public_key: PublicKey,
+    encrypted_code: Option<Vec<u8>>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SeriesCodesJson {
+    pub total: U64,
+    pub claimed: U64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CodeClaimJson {
+    pub token_id: TokenId,
+    pub slot: U64,
+    pub code_hash: Base64VecU8,
+    pub claimed_by: AccountId,
+    pub claimed_at: TimestampSec,
+    pub public_key: PublicKey,
+    pub encrypted_code: Option<Base64VecU8>,
+}
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn nft_commit_series_codes(
+        &mut self,
+        token_series_id: TokenSeriesId,
+        code_hashes: Vec<Base64VecU8>,
+    ) -> SeriesCodesJson {
+        let initial_storage_usage = env::storage_usage();
+
+        let token_series = self
+            .token_series_by_id
+            .get(&token_series_id)
+            .expect("Marketplace: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "Marketplace: Creator only"
+        );
+
+        let mut series_codes = self
+            .codes_by_series
+            .get(&token_series_id)
+            .unwrap_or_else(|| SeriesCodes {
+                code_hashes: Vector::new(StorageKey::CodeHashesBySeriesInner {
+                    token_series: token_series_id.clone(),
+                }),
+                claimed: 0,
+            });
+
+        let from_slot = series_codes.code_hashes.len();
+        for code_hash in code_hashes.iter() {
+            let code_hash: CryptoHash = code_hash
+                .0
+                .as_slice()
+                .try_into()
+                .expect("Marketplace: code hash must be 32 bytes");
+            series_codes.code_hashes.push(&code_hash);
+        }
+
+        self.codes_by_series.insert(&token_series_id, &series_codes);
+
+        // Hashes are read from the transaction, a whole batch can exceed the log limit
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"nft_commit_series_codes",
+            "data": {
+                    "token_series_id": token_series_id,
+                    "from_slot": U64::from(from_slot),
+                    "count": U64::from(code_hashes.len() as u64),
+                    "total": U64::from(series_codes.code_hashes.len()),
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+
+        refund_deposit(env::storage_usage() - initial_storage_usage, 0);
+
+        SeriesCodesJson {
+            total: series_codes.code_hashes.len().into(),
+            claimed: series_codes.claimed.into(),
+        }
+    }
+
+    #[payable]
+    pub fn nft_claim_code(&mut self, token_id: TokenId, public_key: PublicKey) -> U64 {
+        let initial_storage_usage = env::storage_usage();
+
+        let owner_id = self
+            .tokens
+            .owner_by_id
+            .get(&token_id)
+            .expect("Marketplace: Token doesn't exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            owner_id,
+            "Marketplace: Token owner only"
+        );
+        assert!(
+            self.code_claim_by_token.get(&token_id).is_none(),
+            "Marketplace: Code already claimed for token"
+        );
+
+        let token_series_id: TokenSeriesId =
+            token_id.split(TOKEN_DELIMETER).next().unwrap().to_string();
+        let mut series_codes = self
+            .codes_by_series
+            .get(&token_series_id)
+            .expect("Marketplace: Token series has no codes");
+        assert!(
+            series_codes.claimed < series_codes.code_hashes.len(),
+            "Marketplace: All codes already claimed"
+        );
+
+        let slot = series_codes.claimed;
+        series_codes.claimed += 1;
+        self.codes_by_series.insert(&token_series_id, &series_codes);
+
+        self.code_claim_by_token.insert(
+            &token_id,
+            &CodeClaim {
+                slot,
+                claimed_by: owner_id.clone(),
+                claimed_at: to_sec(env::block_timestamp()),
+                public_key: public_key.clone(),
+                encrypted_code: None,
+            },
+        );
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"nft_claim_code",
+            "data": {
+                    "token_series_id": token_series_id,
+                    "token_id": token_id,
+                    "owner_id": owner_id,
+                    "slot": U64::from(slot),
+                    "public_key": public_key,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+
+        refund_deposit(env::storage_usage() - initial_storage_usage, 0);
+
+        U64::from(slot)
+    }
+
+    // The holder checks the decrypted salt and code against the committed hash
+    #[payable]
+    pub fn nft_reveal_code(&mut self, token_id: TokenId, encrypted_code: Base64VecU8) {
+        let initial_storage_usage = env::storage_usage();
+
+        let token_series_id: TokenSeriesId =
+            token_id.split(TOKEN_DELIMETER).next().unwrap().to_string();
+        let token_series = self
+            .token_series_by_id
+            .get(&token_series_id)
+            .expect("Marketplace: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "Marketplace: Creator only"
+        );
+
+        let mut claim = self
+            .code_claim_by_token
+            .get(&token_id)
+            .expect("Marketplace: Code not requested by token owner");
+        assert!(
+            claim.encrypted_code.is_none(),
+            "Marketplace: Code already revealed"
+        );
+        assert!(
+            !encrypted_code.0.is_empty(),
+            "Marketplace: Encrypted code is empty"
+        );
+
+        claim.encrypted_code = Some(encrypted_code.0);
+        self.code_claim_by_token.insert(&token_id, &claim);
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"nft_reveal_code",
+            "data": {
+                    "token_series_id": token_series_id,
+                    "token_id": token_id,
+                    "slot": U64::from(claim.slot),
+                    "claimed_by": claim.claimed_by,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+
+        refund_deposit(env::storage_usage() - initial_storage_usage, 0);
+    }
+
+    pub fn nft_series_codes(&self, token_series_id: TokenSeriesId) -> SeriesCodesJson {
+        self.codes_by_series
+            .get(&token_series_id)
+            .map(|series_codes| SeriesCodesJson {
+                total: series_codes.code_hashes.len().into(),
+                claimed: series_codes.claimed.into(),
+            })
+            .unwrap_or(SeriesCodesJson {
+                total: 0.into(),
+                claimed: 0.into(),
+            })
+    }
+
+    pub fn nft_code_claim(&self, token_id: TokenId) -> Option<CodeClaimJson> {
+        let claim = self.code_claim_by_token.get(&token_id)?;
+        let token_series_id: TokenSeriesId =
+            token_id.split(TOKEN_DELIMETER).next().unwrap().to_string();
+        let code_hash = self
+            .codes_by_series
+            .get(&token_series_id)?
+            .code_hashes
+            .get(claim.slot)?;
+
+        Some(CodeClaimJson {
+            token_id,
+            slot: claim.slot.into(),
+            code_hash: code_hash.to_vec().into(),
+            claimed_by: claim.claimed_by,
+            claimed_at: claim.claimed_at,
+            public_key: claim.public_key,
+            encrypted_code: claim.encrypted_code.map(Base64VecU8::from),
+        })
+    }
+}
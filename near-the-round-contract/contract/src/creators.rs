@@ -0,0 +1,272 @@
+use crate::*;
+use near_sdk::{
+    borsh::{BorshDeserialize, BorshSerialize},
+    near_bindgen,
+    serde::{Deserialize, Serialize},
+    AccountId,
+};
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub struct CreatorApplication {
+    pub account_id: AccountId,
+    pub display_name: String,
+    pub payout_id: Option<AccountId>,
+    // URL to an off-chain JSON file with the portfolio and contact details
+    pub reference: Option<String>,
+    pub submitted_at: TimestampSec,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub struct CreatorProfile {
+    pub display_name: String,
+    // Receives primary sale proceeds instead of the creator account
+    pub payout_id: Option<AccountId>,
+    pub verified: bool,
+}
+
+#[near_bindgen]
+impl Contract {
+    // Admins
+    #[payable]
+    pub fn add_admin(&mut self, account_id: AccountId) -> TimelockOperation {
+        self.schedule_timelock_operation(TimelockAction::AddAdmin { account_id })
+    }
+
+    #[payable]
+    pub fn remove_admin(&mut self, account_id: AccountId) {
+        assert_one_yocto();
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.tokens.owner_id,
+            "Marketplace: Owner only"
+        );
+        self.admins.remove(&account_id);
+    }
+
+    pub fn get_admins(&self) -> Vec<AccountId> {
+        self.admins.to_vec()
+    }
+
+    // Applications
+    #[payable]
+    pub fn submit_creator_application(
+        &mut self,
+        display_name: String,
+        payout_id: Option<AccountId>,
+        reference: Option<String>,
+    ) -> CreatorApplication {
+        let initial_storage_usage = env::storage_usage();
+        let account_id = env::predecessor_account_id();
+
+        assert!(
+            !self.whitelisted_creators.contains(&account_id),
+            "Marketplace: Already a creator"
+        );
+        assert!(
+            self.creator_applications.get(&account_id).is_none(),
+            "Marketplace: Application already submitted"
+        );
+
+        let application = CreatorApplication {
+            account_id: account_id.clone(),
+            display_name,
+            payout_id,
+            reference,
+            submitted_at: to_sec(env::block_timestamp()),
+        };
+        self.creator_applications.insert(&account_id, &application);
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"creator_application_submit",
+            "data": &application
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+
+        refund_deposit(env::storage_usage() - initial_storage_usage, 0);
+
+        application
+    }
+
+    #[payable]
+    pub fn withdraw_creator_application(&mut self) {
+        assert_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+        let account_id = env::predecessor_account_id();
+
+        self.creator_applications
+            .remove(&account_id)
+            .expect("Marketplace: Application not exist");
+
+        refund_released_storage(&account_id, initial_storage_usage - env::storage_usage());
+    }
+
+    #[payable]
+    pub fn approve_creator_application(&mut self, account_id: AccountId, verified: bool) {
+        assert_one_yocto();
+        self.internal_assert_admin();
+        let initial_storage_usage = env::storage_usage();
+
+        let application = self
+            .creator_applications
+            .remove(&account_id)
+            .expect("Marketplace: Application not exist");
+
+        self.whitelisted_creators.insert(&account_id);
+        self.creator_profiles.insert(
+            &account_id,
+            &CreatorProfile {
+                display_name: application.display_name,
+                payout_id: application.payout_id,
+                verified,
+            },
+        );
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"creator_application_approve",
+            "data": {
+                    "account_id": account_id,
+                    "verified": verified,
+                    "admin_id": env::predecessor_account_id(),
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+
+        // The application deposit carries over to the profile, only the rest goes back
+        refund_released_storage(
+            &account_id,
+            initial_storage_usage.saturating_sub(env::storage_usage()),
+        );
+    }
+
+    #[payable]
+    pub fn reject_creator_application(&mut self, account_id: AccountId, reason: Option<String>) {
+        assert_one_yocto();
+        self.internal_assert_admin();
+        let initial_storage_usage = env::storage_usage();
+
+        self.creator_applications
+            .remove(&account_id)
+            .expect("Marketplace: Application not exist");
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"creator_application_reject",
+            "data": {
+                    "account_id": account_id,
+                    "reason": reason,
+                    "admin_id": env::predecessor_account_id(),
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+
+        refund_released_storage(&account_id, initial_storage_usage - env::storage_usage());
+    }
+
+    pub fn get_creator_application(&self, account_id: AccountId) -> Option<CreatorApplication> {
+        self.creator_applications.get(&account_id)
+    }
+
+    pub fn get_creator_applications(
+        &self,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<CreatorApplication> {
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        assert_ne!(limit, 0, "Cannot provide limit of 0.");
+
+        self.creator_applications
+            .values()
+            .skip(start_index as usize)
+            .take(limit)
+            .collect()
+    }
+
+    // Profiles
+    #[payable]
+    pub fn set_creator_profile(&mut self, display_name: String, payout_id: Option<AccountId>) {
+        let initial_storage_usage = env::storage_usage();
+        let creator_id = env::predecessor_account_id();
+
+        assert!(
+            self.whitelisted_creators.contains(&creator_id),
+            "Marketplace: Creator must be whitelisted"
+        );
+
+        let verified = self
+            .creator_profiles
+            .get(&creator_id)
+            .is_some_and(|profile| profile.verified);
+        self.creator_profiles.insert(
+            &creator_id,
+            &CreatorProfile {
+                display_name,
+                payout_id,
+                verified,
+            },
+        );
+
+        refund_deposit(
+            env::storage_usage().saturating_sub(initial_storage_usage),
+            0,
+        );
+    }
+
+    #[payable]
+    pub fn set_creator_verified(&mut self, creator_id: AccountId, verified: bool) {
+        assert_one_yocto();
+        self.internal_assert_admin();
+
+        let mut profile = self
+            .creator_profiles
+            .get(&creator_id)
+            .expect("Marketplace: Creator profile not exist");
+        profile.verified = verified;
+        self.creator_profiles.insert(&creator_id, &profile);
+    }
+
+    pub fn get_creator_profile(&self, creator_id: AccountId) -> Option<CreatorProfile> {
+        self.creator_profiles.get(&creator_id)
+    }
+
+    pub(crate) fn internal_assert_admin(&self) {
+        assert!(
+            env::predecessor_account_id() == self.tokens.owner_id
+                || self.admins.contains(&env::predecessor_account_id()),
+            "Marketplace: Admin only"
+        );
+    }
+
+    // Series of creators removed from the whitelist can no longer be minted
+    pub(crate) fn internal_is_active_creator(&self, creator_id: &AccountId) -> bool {
+        *creator_id == self.tokens.owner_id || self.whitelisted_creators.contains(creator_id)
+    }
+
+    pub(crate) fn internal_payout_id(&self, creator_id: &AccountId) -> AccountId {
+        self.creator_profiles
+            .get(creator_id)
+            .and_then(|profile| profile.payout_id)
+            .unwrap_or_else(|| creator_id.clone())
+    }
+}
@@ -0,0 +1,216 @@
+use crate::*;
+use near_sdk::{
+    assert_one_yocto,
+    borsh::{BorshDeserialize, BorshSerialize},
+    json_types::Base64VecU8,
+    near_bindgen,
+    serde::{Deserialize, Serialize},
+    CryptoHash,
+};
+
+#[derive(
+    BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, Debug, PartialEq,
+)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub enum Discount {
+    // Basis points off the series price
+    Percent(u16),
+    Absolute(U128),
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub struct DiscountCode {
+    pub discount: Discount,
+    pub max_uses: Option<u32>,
+    pub uses: u32,
+    pub expires_at: Option<TimestampSec>,
+}
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn nft_add_discount_code(
+        &mut self,
+        token_series_id: TokenSeriesId,
+        code_hash: Base64VecU8,
+        discount: Discount,
+        max_uses: Option<u32>,
+        expires_at: Option<TimestampSec>,
+    ) -> DiscountCode {
+        let initial_storage_usage = env::storage_usage();
+
+        let token_series = self
+            .token_series_by_id
+            .get(&token_series_id)
+            .expect("Marketplace: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "Marketplace: Creator only"
+        );
+        if let Discount::Percent(basis_points) = discount {
+            assert!(
+                basis_points <= 10_000,
+                "Marketplace: discount is more than 10_000"
+            );
+        }
+
+        let hash: CryptoHash = code_hash
+            .0
+            .as_slice()
+            .try_into()
+            .expect("Marketplace: code hash must be 32 bytes");
+        assert!(
+            self.discount_codes
+                .get(&(token_series_id.clone(), hash))
+                .is_none(),
+            "Marketplace: Discount code already exists"
+        );
+
+        let discount_code = DiscountCode {
+            discount,
+            max_uses,
+            uses: 0,
+            expires_at,
+        };
+        self.discount_codes
+            .insert(&(token_series_id.clone(), hash), &discount_code);
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"nft_add_discount_code",
+            "data": {
+                    "token_series_id": token_series_id,
+                    "code_hash": code_hash,
+                    "discount": discount,
+                    "max_uses": max_uses,
+                    "expires_at": expires_at,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+
+        refund_deposit(env::storage_usage() - initial_storage_usage, 0);
+
+        discount_code
+    }
+
+    #[payable]
+    pub fn nft_remove_discount_code(
+        &mut self,
+        token_series_id: TokenSeriesId,
+        code_hash: Base64VecU8,
+    ) {
+        assert_one_yocto();
+
+        let token_series = self
+            .token_series_by_id
+            .get(&token_series_id)
+            .expect("Marketplace: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "Marketplace: Creator only"
+        );
+
+        let hash: CryptoHash = code_hash
+            .0
+            .as_slice()
+            .try_into()
+            .expect("Marketplace: code hash must be 32 bytes");
+        self.discount_codes
+            .remove(&(token_series_id.clone(), hash))
+            .expect("Marketplace: Discount code not exist");
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"nft_remove_discount_code",
+            "data": {
+                    "token_series_id": token_series_id,
+                    "code_hash": code_hash,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+    }
+
+    pub fn nft_get_discount_code(
+        &self,
+        token_series_id: TokenSeriesId,
+        code_hash: Base64VecU8,
+    ) -> Option<DiscountCode> {
+        let hash: CryptoHash = code_hash.0.as_slice().try_into().ok()?;
+        self.discount_codes.get(&(token_series_id, hash))
+    }
+
+    #[payable]
+    pub fn nft_buy_with_code(&mut self, token_series_id: TokenSeriesId, code: String) -> Token {
+        let token_series = self
+            .token_series_by_id
+            .get(&token_series_id)
+            .expect("Marketplace: Token series not exist");
+
+        let price: u128 = token_series.price.expect("Marketplace: not for sale");
+
+        let hash = env::sha256_array(code.as_bytes());
+        let mut discount_code = self
+            .discount_codes
+            .get(&(token_series_id.clone(), hash))
+            .expect("Marketplace: Invalid discount code");
+        if let Some(expires_at) = discount_code.expires_at {
+            assert!(
+                to_sec(env::block_timestamp()) < expires_at,
+                "Marketplace: Discount code expired"
+            );
+        }
+        if let Some(max_uses) = discount_code.max_uses {
+            assert!(
+                discount_code.uses < max_uses,
+                "Marketplace: Discount code used up"
+            );
+        }
+
+        let discounted_price = match discount_code.discount {
+            Discount::Percent(basis_points) => price - price * basis_points as u128 / 10_000u128,
+            Discount::Absolute(amount) => price.saturating_sub(amount.0),
+        };
+
+        discount_code.uses += 1;
+        self.discount_codes
+            .insert(&(token_series_id.clone(), hash), &discount_code);
+
+        let token = self.internal_buy(&token_series_id, &token_series, discounted_price);
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"nft_use_discount_code",
+            "data": {
+                    "token_series_id": token_series_id,
+                    "code_hash": Base64VecU8(hash.to_vec()),
+                    "token_id": token.token_id,
+                    "price": U128(price),
+                    "discounted_price": U128(discounted_price),
+                    "uses": discount_code.uses,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+
+        token
+    }
+}
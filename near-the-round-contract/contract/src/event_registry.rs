@@ -0,0 +1,277 @@
+use crate::*;
+use near_sdk::{
+    assert_one_yocto,
+    borsh::{BorshDeserialize, BorshSerialize},
+    near_bindgen,
+    serde::{Deserialize, Serialize},
+    AccountId,
+};
+
+pub type EventId = String;
+
+#[derive(
+    BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, Debug, PartialEq,
+)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub enum EventStatus {
+    Scheduled,
+    Live,
+    Ended,
+    Cancelled,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub struct Event {
+    pub event_id: EventId,
+    pub name: String,
+    pub venue: Option<String>,
+    pub start_time: TimestampSec,
+    pub end_time: TimestampSec,
+    pub organiser_id: AccountId,
+    pub status: EventStatus,
+}
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn create_event(
+        &mut self,
+        name: String,
+        venue: Option<String>,
+        start_time: TimestampSec,
+        end_time: TimestampSec,
+    ) -> Event {
+        assert!(
+            self.whitelisted_creators
+                .contains(&env::predecessor_account_id())
+                || env::predecessor_account_id() == self.tokens.owner_id,
+            "Marketplace: Creator must be whitelisted"
+        );
+        assert!(
+            start_time < end_time,
+            "Marketplace: start_time is greater than end_time"
+        );
+
+        let initial_storage_usage = env::storage_usage();
+        let organiser_id = env::predecessor_account_id();
+        let event_id = format!("{}", (self.events_by_id.len() + 1));
+
+        let event = Event {
+            event_id: event_id.clone(),
+            name,
+            venue,
+            start_time,
+            end_time,
+            organiser_id: organiser_id.clone(),
+            status: EventStatus::Scheduled,
+        };
+        self.events_by_id.insert(&event_id, &event);
+
+        let mut organiser_events =
+            self.events_by_organiser
+                .get(&organiser_id)
+                .unwrap_or_else(|| {
+                    UnorderedSet::new(StorageKey::EventsByOrganiserInner {
+                        account_id_hash: env::sha256(organiser_id.as_bytes()),
+                    })
+                });
+        organiser_events.insert(&event_id);
+        self.events_by_organiser
+            .insert(&organiser_id, &organiser_events);
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"event_create",
+            "data": &event
+        })
+        .to_string();
+
+        let event_log = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event_log);
+
+        refund_deposit(env::storage_usage() - initial_storage_usage, 0);
+
+        event
+    }
+
+    #[payable]
+    pub fn set_event_status(&mut self, event_id: EventId, status: EventStatus) {
+        assert_one_yocto();
+
+        let mut event = self
+            .events_by_id
+            .get(&event_id)
+            .expect("Marketplace: Event not exist");
+        assert!(
+            env::predecessor_account_id() == event.organiser_id
+                || env::predecessor_account_id() == self.tokens.owner_id,
+            "Marketplace: Not allowed"
+        );
+
+        let is_valid_transition = matches!(
+            (event.status, status),
+            (EventStatus::Scheduled, EventStatus::Live)
+                | (EventStatus::Live, EventStatus::Ended)
+                | (EventStatus::Scheduled, EventStatus::Cancelled)
+                | (EventStatus::Live, EventStatus::Cancelled)
+        );
+        assert!(
+            is_valid_transition,
+            "Marketplace: Cannot change event status from {:?} to {:?}",
+            event.status, status
+        );
+
+        event.status = status;
+        self.events_by_id.insert(&event_id, &event);
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"event_set_status",
+            "data": {
+                    "event_id": event_id,
+                    "status": status,
+            }
+        })
+        .to_string();
+
+        let event_log = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event_log);
+    }
+
+    #[payable]
+    pub fn nft_set_series_event(
+        &mut self,
+        token_series_id: TokenSeriesId,
+        event_id: Option<EventId>,
+    ) {
+        let initial_storage_usage = env::storage_usage();
+
+        let mut token_series = self
+            .token_series_by_id
+            .get(&token_series_id)
+            .expect("Marketplace: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "Marketplace: Creator only"
+        );
+
+        if let Some(previous_event_id) = &token_series.event_id {
+            let mut event_series = self.series_by_event.get(previous_event_id).unwrap();
+            event_series.remove(&token_series_id);
+            self.series_by_event
+                .insert(previous_event_id, &event_series);
+        }
+
+        if let Some(event_id) = &event_id {
+            let event = self
+                .events_by_id
+                .get(event_id)
+                .expect("Marketplace: Event not exist");
+            assert_eq!(
+                env::predecessor_account_id(),
+                event.organiser_id,
+                "Marketplace: Organiser only"
+            );
+
+            let mut event_series = self.series_by_event.get(event_id).unwrap_or_else(|| {
+                UnorderedSet::new(StorageKey::SeriesByEventInner {
+                    event_id: event_id.clone(),
+                })
+            });
+            event_series.insert(&token_series_id);
+            self.series_by_event.insert(event_id, &event_series);
+        }
+
+        token_series.event_id = event_id.clone();
+        self.token_series_by_id
+            .insert(&token_series_id, &token_series);
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"nft_set_series_event",
+            "data": {
+                    "token_series_id": token_series_id,
+                    "event_id": event_id,
+            }
+        })
+        .to_string();
+
+        let event_log = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event_log);
+
+        refund_deposit(
+            env::storage_usage().saturating_sub(initial_storage_usage),
+            0,
+        );
+    }
+
+    pub fn get_event(&self, event_id: EventId) -> Option<Event> {
+        self.events_by_id.get(&event_id)
+    }
+
+    pub fn get_events_by_organiser(
+        &self,
+        organiser_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<Event> {
+        let organiser_events = match self.events_by_organiser.get(&organiser_id) {
+            Some(organiser_events) => organiser_events,
+            None => return vec![],
+        };
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        assert_ne!(limit, 0, "Cannot provide limit of 0.");
+
+        organiser_events
+            .iter()
+            .skip(start_index as usize)
+            .take(limit)
+            .map(|event_id| self.events_by_id.get(&event_id).unwrap())
+            .collect()
+    }
+
+    pub fn get_series_by_event(
+        &self,
+        event_id: EventId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<TokenSeriesJson> {
+        let event_series = match self.series_by_event.get(&event_id) {
+            Some(event_series) => event_series,
+            None => return vec![],
+        };
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        assert_ne!(limit, 0, "Cannot provide limit of 0.");
+
+        event_series
+            .iter()
+            .skip(start_index as usize)
+            .take(limit)
+            .map(|token_series_id| self.nft_get_series_single(token_series_id))
+            .collect()
+    }
+
+    pub(crate) fn internal_assert_event_on_sale(&self, event_id: &EventId) {
+        let event = self
+            .events_by_id
+            .get(event_id)
+            .expect("Marketplace: Event not exist");
+        assert!(
+            matches!(event.status, EventStatus::Scheduled | EventStatus::Live),
+            "Marketplace: Event is {:?}",
+            event.status
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use crate::*;
+use near_sdk::{
+    assert_one_yocto,
+    borsh::{BorshDeserialize, BorshSerialize},
+    near_bindgen,
+    serde::{Deserialize, Serialize},
+    AccountId,
+};
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub struct SeriesGate {
+    // Series the buyer has to hold tokens of
+    pub token_series_id: TokenSeriesId,
+    // Number of tokens of that series the buyer has to hold
+    pub count: u32,
+    // Every purchase consumes `count` gating tokens, which can't be used again for this series
+    pub one_per_token: bool,
+}
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn set_nft_series_gate(
+        &mut self,
+        token_series_id: TokenSeriesId,
+        gate: Option<SeriesGate>,
+    ) {
+        assert_one_yocto();
+
+        let mut token_series = self
+            .token_series_by_id
+            .get(&token_series_id)
+            .expect("Marketplace: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "Marketplace: Creator only"
+        );
+
+        if let Some(gate) = &gate {
+            assert_ne!(
+                gate.token_series_id, token_series_id,
+                "Marketplace: Token series cannot gate itself"
+            );
+            assert!(
+                self.token_series_by_id.get(&gate.token_series_id).is_some(),
+                "Marketplace: Gating token series not exist"
+            );
+            assert!(gate.count > 0, "Marketplace: Gate count must be positive");
+        }
+
+        token_series.gate = gate.clone();
+        self.token_series_by_id
+            .insert(&token_series_id, &token_series);
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"nft_set_series_gate",
+            "data": {
+                    "token_series_id": token_series_id,
+                    "gate": gate,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+    }
+
+    pub fn nft_is_gate_token_consumed(
+        &self,
+        token_series_id: TokenSeriesId,
+        token_id: TokenId,
+    ) -> bool {
+        self.gate_consumed_tokens
+            .contains(&(token_series_id, token_id))
+    }
+
+    pub(crate) fn internal_assert_gate(
+        &mut self,
+        token_series_id: &TokenSeriesId,
+        token_series: &TokenSeries,
+        account_id: &AccountId,
+    ) {
+        let gate = match &token_series.gate {
+            Some(gate) => gate,
+            None => return,
+        };
+
+        let held = self.internal_gate_held_tokens(gate, account_id);
+
+        assert!(
+            held.len() as u32 >= gate.count,
+            "Marketplace: Must hold {} tokens of series {}",
+            gate.count,
+            gate.token_series_id
+        );
+
+        if gate.one_per_token {
+            let unused: Vec<TokenId> = held
+                .into_iter()
+                .filter(|token_id| {
+                    !self
+                        .gate_consumed_tokens
+                        .contains(&(token_series_id.clone(), token_id.clone()))
+                })
+                .take(gate.count as usize)
+                .collect();
+            assert!(
+                unused.len() as u32 >= gate.count,
+                "Marketplace: All gating tokens already used"
+            );
+
+            for token_id in unused {
+                self.gate_consumed_tokens
+                    .insert(&(token_series_id.clone(), token_id.clone()));
+
+                let log_data = &json!({
+                    "standard":"nep171",
+                    "version":"1.0.0",
+                    "event":"nft_gate_token_consumed",
+                    "data": {
+                            "token_series_id": token_series_id,
+                            "gate_token_id": token_id,
+                            "account_id": account_id,
+                    }
+                })
+                .to_string();
+
+                let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+                env::log_str(&event);
+            }
+        }
+    }
+
+    // Tokens of the gating series held by the account
+    pub(crate) fn internal_gate_held_tokens(
+        &self,
+        gate: &SeriesGate,
+        account_id: &AccountId,
+    ) -> Vec<TokenId> {
+        let prefix = format!("{}{}", gate.token_series_id, TOKEN_DELIMETER);
+        self.tokens
+            .tokens_per_owner
+            .as_ref()
+            .and_then(|by_owner| by_owner.get(account_id))
+            .map(|tokens| {
+                tokens
+                    .iter()
+                    .filter(|token_id| token_id.starts_with(&prefix))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,105 @@
+use crate::*;
+use near_sdk::{near_bindgen, AccountId, Gas, PromiseResult};
+
+const GAS_FOR_ON_WITHDRAW: Gas = Gas::from_gas(10_000_000_000_000);
+
+#[near_bindgen]
+impl Contract {
+    // Ledger mode
+    pub fn get_ledger_mode(&self) -> bool {
+        self.ledger_mode
+    }
+
+    #[payable]
+    pub fn set_ledger_mode(&mut self, ledger_mode: bool) {
+        assert_one_yocto();
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.tokens.owner_id,
+            "Marketplace: Owner only"
+        );
+        self.ledger_mode = ledger_mode;
+    }
+
+    pub fn get_pending_balance(&self, account_id: AccountId) -> U128 {
+        self.pending_balances.get(&account_id).unwrap_or(0).into()
+    }
+
+    #[payable]
+    pub fn withdraw(&mut self, amount: Option<U128>) -> Promise {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+
+        let balance = self.pending_balances.get(&account_id).unwrap_or(0);
+        let amount = amount.map(|amount| amount.0).unwrap_or(balance);
+        assert!(amount > 0, "Marketplace: Nothing to withdraw");
+        assert!(
+            amount <= balance,
+            "Marketplace: Pending balance is less than {}",
+            amount
+        );
+
+        self.liabilities.pending_balances -= amount;
+        if balance == amount {
+            self.pending_balances.remove(&account_id);
+        } else {
+            self.pending_balances
+                .insert(&account_id, &(balance - amount));
+        }
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"withdraw",
+            "data": {
+                    "account_id": account_id,
+                    "amount": U128(amount),
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+
+        Promise::new(account_id.clone())
+            .transfer(NearToken::from_yoctonear(amount))
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_ON_WITHDRAW)
+                    .on_withdraw(account_id, amount.into()),
+            )
+    }
+
+    #[private]
+    pub fn on_withdraw(&mut self, account_id: AccountId, amount: U128) -> bool {
+        let is_success = matches!(env::promise_result(0), PromiseResult::Successful(_));
+
+        if !is_success {
+            // The transfer bounced back, so the balance can be withdrawn again
+            self.internal_credit(&account_id, amount.0);
+        }
+
+        is_success
+    }
+
+    // Credits the account in ledger mode, transfers right away otherwise
+    pub(crate) fn internal_pay(&mut self, account_id: &AccountId, amount: Balance) {
+        if amount == 0 {
+            return;
+        }
+
+        if self.ledger_mode {
+            self.internal_credit(account_id, amount);
+        } else {
+            Promise::new(account_id.clone()).transfer(NearToken::from_yoctonear(amount));
+        }
+    }
+
+    fn internal_credit(&mut self, account_id: &AccountId, amount: Balance) {
+        let balance = self.pending_balances.get(account_id).unwrap_or(0);
+        self.pending_balances
+            .insert(account_id, &(balance + amount));
+        self.liabilities.pending_balances += amount;
+    }
+}
@@ -1,38 +1,94 @@
+// The cross-contract helpers near_bindgen generates don't carry method attributes
+#![allow(clippy::too_many_arguments)]
+
 use std::collections::HashMap;
 
+use near_contract_standards::non_fungible_token::approval::NonFungibleTokenApproval;
 use near_contract_standards::non_fungible_token::core::{
     NonFungibleTokenCore, NonFungibleTokenResolver,
 };
+use near_contract_standards::non_fungible_token::enumeration::NonFungibleTokenEnumeration;
 use near_contract_standards::non_fungible_token::events::NftMint;
 use near_contract_standards::non_fungible_token::metadata::{
     NFTContractMetadata, NonFungibleTokenMetadataProvider, TokenMetadata, NFT_METADATA_SPEC,
 };
 use near_contract_standards::non_fungible_token::NonFungibleToken;
 use near_contract_standards::non_fungible_token::{Token, TokenId};
-use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LazyOption, UnorderedMap, UnorderedSet};
+use near_sdk::borsh::{BorshDeserialize, BorshSerialize};
+use near_sdk::collections::{LazyOption, LookupMap, LookupSet, UnorderedMap, UnorderedSet};
 use near_sdk::env::is_valid_account_id;
 use near_sdk::json_types::{U128, U64};
 use near_sdk::serde_json::json;
 use near_sdk::{
-    assert_one_yocto, env, near_bindgen, AccountId, Balance, BorshStorageKey, PanicOnDefault,
-    Promise, PromiseOrValue, Timestamp,
+    assert_one_yocto, env, near_bindgen, AccountId, BorshStorageKey, CryptoHash, NearToken,
+    PanicOnDefault, Promise, PromiseOrValue, PublicKey, Timestamp,
 };
 use serde::{Deserialize, Serialize};
 
-pub use crate::payout::Payout;
-
+use crate::analytics::SalesStats;
+pub use crate::analytics::SalesStatsJson;
+use crate::capacity_pools::CapacityPool;
+pub use crate::capacity_pools::{CapacityPoolId, CapacityPoolJson};
+pub use crate::claim_links::ClaimLink;
+use crate::codes::{CodeClaim, SeriesCodes};
+pub use crate::codes::{CodeClaimJson, SeriesCodesJson};
+pub use crate::creators::{CreatorApplication, CreatorProfile};
+pub use crate::discounts::{Discount, DiscountCode};
+pub use crate::event_registry::{Event, EventId, EventStatus};
+pub use crate::gating::SeriesGate;
+pub use crate::merch::MerchRedemption;
+pub use crate::payout::{Payout, Payouts};
+pub use crate::quotes::PurchaseQuote;
+use crate::quotes::SaleSplit;
+pub use crate::receipts::{ReceiptId, SaleReceipt, SaleType};
+pub use crate::renewals::{PassRenewal, RenewalDeposit};
+pub use crate::series_kinds::SeriesKind;
+use crate::solvency::Liabilities;
+pub use crate::solvency::{LiabilitiesJson, SolvencyJson};
+pub use crate::timelock::{
+    TimelockAction, TimelockOperation, TimelockOperationId, TIMELOCK_FEE_THRESHOLD,
+};
+pub use crate::upgrades::UpgradePath;
+pub use crate::venues::{CheckIn, PassUses, Venue, VenueId};
+pub use crate::vouchers::MintVoucher;
+
+mod analytics;
+mod capacity_pools;
+mod claim_links;
+mod codes;
+mod creators;
+mod discounts;
+mod event_registry;
+mod gating;
+mod ledger;
+mod merch;
+mod migration;
+mod ownership;
 mod payout;
+mod quotes;
+mod receipts;
+mod renewals;
+mod series_kinds;
+mod solvency;
+mod timelock;
+mod upgrades;
+mod venues;
+mod vouchers;
 
 pub const TOKEN_DELIMETER: char = ':';
 pub const TITLE_DELIMETER: &str = " #";
 
 const MAX_PRICE: Balance = 1_000_000_000 * 10u128.pow(24);
+// Highest transaction fee in basis points, leaving room for the venue fee
+const MAX_TRANSACTION_FEE: u16 = 5_000;
+const MAX_SCHEDULED_FEES: usize = 10;
 
 pub type TokenSeriesId = String;
 pub type TimestampSec = u32;
+pub type Balance = u128;
 
 #[derive(BorshDeserialize, BorshSerialize)]
+#[borsh(crate = "near_sdk::borsh")]
 pub struct TokenSeries {
     metadata: TokenMetadata,
     creator_id: AccountId,
@@ -42,6 +98,16 @@ pub struct TokenSeries {
     royalty: HashMap<AccountId, u32>,
     start_time: Option<TimestampSec>,
     end_time: Option<TimestampSec>,
+    gate: Option<SeriesGate>,
+    event_id: Option<EventId>,
+    venue_id: Option<VenueId>,
+    capacity_pool_id: Option<CapacityPoolId>,
+    primary_split: HashMap<AccountId, u32>,
+    royalty_paid_on_buy: bool,
+    kind: SeriesKind,
+    pass_uses: Option<PassUses>,
+    renewal: Option<PassRenewal>,
+    upgrade_paths: HashMap<TokenSeriesId, UpgradePath>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -54,38 +120,102 @@ pub struct TokenSeriesJson {
     transaction_fee: Option<U128>,
     start_time: Option<TimestampSec>,
     end_time: Option<TimestampSec>,
+    gate: Option<SeriesGate>,
+    event_id: Option<EventId>,
+    venue_id: Option<VenueId>,
+    capacity_pool_id: Option<CapacityPoolId>,
+    primary_split: HashMap<AccountId, u32>,
+    royalty_paid_on_buy: bool,
+    kind: SeriesKind,
+    pass_uses: Option<PassUses>,
+    renewal: Option<PassRenewal>,
+    upgrade_paths: HashMap<TokenSeriesId, UpgradePath>,
 }
 
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[borsh(crate = "near_sdk::borsh")]
 #[serde(crate = "near_sdk::serde")]
 pub struct TransactionFee {
     pub next_fee: Option<u16>,
     pub start_time: Option<TimestampSec>,
     pub current_fee: u16,
+    // Fee changes after next_fee, ordered by start_time
+    pub queued_fees: Vec<ScheduledFee>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub struct ScheduledFee {
+    pub fee: u16,
+    pub start_time: TimestampSec,
 }
 
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+#[borsh(crate = "near_sdk::borsh")]
 pub struct MarketDataTransactionFee {
     pub transaction_fee: UnorderedMap<TokenSeriesId, u128>,
 }
 
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+#[borsh(crate = "near_sdk::borsh")]
 pub struct Contract {
     tokens: NonFungibleToken,
     metadata: LazyOption<NFTContractMetadata>,
     // Additional data for the contract
     token_series_by_id: UnorderedMap<TokenSeriesId, TokenSeries>,
-    treasury_id: AccountId,
+    // Treasury recipients weighted in basis points, summing to 10_000
+    treasury: HashMap<AccountId, u32>,
     transaction_fee: TransactionFee,
     market_data_transaction_fee: MarketDataTransactionFee,
     whitelisted_creators: UnorderedSet<AccountId>,
     royalty_paid_on_buy: bool,
+    gate_consumed_tokens: LookupSet<(TokenSeriesId, TokenId)>,
+    codes_by_series: LookupMap<TokenSeriesId, SeriesCodes>,
+    code_claim_by_token: LookupMap<TokenId, CodeClaim>,
+    claim_links: LookupMap<PublicKey, ClaimLink>,
+    claim_link_reservations: LookupMap<TokenSeriesId, u64>,
+    voucher_public_keys: LookupMap<AccountId, PublicKey>,
+    used_voucher_nonces: LookupSet<(AccountId, u64)>,
+    events_by_id: UnorderedMap<EventId, Event>,
+    events_by_organiser: LookupMap<AccountId, UnorderedSet<EventId>>,
+    series_by_event: LookupMap<EventId, UnorderedSet<TokenSeriesId>>,
+    venues_by_id: UnorderedMap<VenueId, Venue>,
+    venue_operators: LookupSet<(VenueId, AccountId)>,
+    checked_in_tokens: LookupMap<TokenId, CheckIn>,
+    capacity_pools: UnorderedMap<CapacityPoolId, CapacityPool>,
+    discount_codes: LookupMap<(TokenSeriesId, CryptoHash), DiscountCode>,
+    ledger_mode: bool,
+    pending_balances: LookupMap<AccountId, Balance>,
+    liabilities: Liabilities,
+    creator_transaction_fees: LookupMap<AccountId, u16>,
+    admins: UnorderedSet<AccountId>,
+    creator_applications: UnorderedMap<AccountId, CreatorApplication>,
+    creator_profiles: LookupMap<AccountId, CreatorProfile>,
+    proposed_owner_id: Option<AccountId>,
+    timelock_operations: UnorderedMap<TimelockOperationId, TimelockOperation>,
+    timelock_nonce: u64,
+    secondary_fee: u16,
+    receipts_by_id: LookupMap<ReceiptId, SaleReceipt>,
+    receipts_by_token: LookupMap<TokenId, Vec<ReceiptId>>,
+    receipts_by_creator: LookupMap<AccountId, Vec<ReceiptId>>,
+    receipts_by_series: LookupMap<TokenSeriesId, Vec<ReceiptId>>,
+    receipts_nonce: u64,
+    sales_stats: SalesStats,
+    series_sales_stats: LookupMap<TokenSeriesId, SalesStats>,
+    creator_sales_stats: LookupMap<AccountId, SalesStats>,
+    merch_redemptions: LookupMap<TokenId, MerchRedemption>,
+    burned_tokens_by_series: LookupMap<TokenSeriesId, u64>,
+    upgrade_credits: LookupMap<TokenId, Balance>,
+    pass_expiries: LookupMap<TokenId, TimestampSec>,
+    renewal_deposits: LookupMap<TokenId, RenewalDeposit>,
 }
 
 const DATA_IMAGE_SVG_NFT_ICON: &str = "";
 
 #[derive(BorshSerialize, BorshStorageKey)]
+#[borsh(crate = "near_sdk::borsh")]
 enum StorageKey {
     NonFungibleToken,
     Metadata,
@@ -97,6 +227,42 @@ enum StorageKey {
     TokensBySeriesInner { token_series: String },
     MarketDataTransactionFee,
     WhitelistedCreators,
+    GateConsumedTokens,
+    CodesBySeries,
+    CodeHashesBySeriesInner { token_series: String },
+    CodeClaimByToken,
+    ClaimLinks,
+    VoucherPublicKeys,
+    UsedVoucherNonces,
+    EventsById,
+    EventsByOrganiser,
+    EventsByOrganiserInner { account_id_hash: Vec<u8> },
+    SeriesByEvent,
+    SeriesByEventInner { event_id: String },
+    VenuesById,
+    VenueOperators,
+    CheckedInTokens,
+    CapacityPools,
+    CapacityPoolSeriesInner { capacity_pool_id: String },
+    DiscountCodes,
+    PendingBalances,
+    CreatorTransactionFees,
+    Admins,
+    CreatorApplications,
+    CreatorProfiles,
+    TimelockOperations,
+    ReceiptsById,
+    ReceiptsByToken,
+    ReceiptsByCreator,
+    ReceiptsBySeries,
+    SeriesSalesStats,
+    CreatorSalesStats,
+    MerchRedemptions,
+    PassExpiries,
+    RenewalDeposits,
+    ClaimLinkReservations,
+    BurnedTokensBySeries,
+    UpgradeCredits,
 }
 
 #[near_bindgen]
@@ -133,27 +299,90 @@ impl Contract {
         royalty_paid_on_buy: bool,
     ) -> Self {
         metadata.assert_valid();
-        Self {
-            tokens: NonFungibleToken::new(
+        Self::internal_new(
+            NonFungibleToken::new(
                 StorageKey::NonFungibleToken,
                 owner_id,
                 Some(StorageKey::TokenMetadata),
                 Some(StorageKey::Enumeration),
                 Some(StorageKey::Approval),
             ),
-            token_series_by_id: UnorderedMap::new(StorageKey::TokenSeriesById),
-            metadata: LazyOption::new(StorageKey::Metadata, Some(&metadata)),
-            treasury_id: treasury_id,
-            transaction_fee: TransactionFee {
+            LazyOption::new(StorageKey::Metadata, Some(&metadata)),
+            UnorderedMap::new(StorageKey::TokenSeriesById),
+            HashMap::from([(treasury_id, 10_000)]),
+            TransactionFee {
                 next_fee: None,
                 start_time: None,
                 current_fee,
+                queued_fees: vec![],
             },
-            market_data_transaction_fee: MarketDataTransactionFee {
+            MarketDataTransactionFee {
                 transaction_fee: UnorderedMap::new(StorageKey::MarketDataTransactionFee),
             },
-            whitelisted_creators: UnorderedSet::new(StorageKey::WhitelistedCreators),
+            UnorderedSet::new(StorageKey::WhitelistedCreators),
+            royalty_paid_on_buy,
+        )
+    }
+
+    // State that predates the first release is passed in, everything else starts empty
+    pub(crate) fn internal_new(
+        tokens: NonFungibleToken,
+        metadata: LazyOption<NFTContractMetadata>,
+        token_series_by_id: UnorderedMap<TokenSeriesId, TokenSeries>,
+        treasury: HashMap<AccountId, u32>,
+        transaction_fee: TransactionFee,
+        market_data_transaction_fee: MarketDataTransactionFee,
+        whitelisted_creators: UnorderedSet<AccountId>,
+        royalty_paid_on_buy: bool,
+    ) -> Self {
+        Self {
+            tokens,
+            token_series_by_id,
+            metadata,
+            treasury,
+            transaction_fee,
+            market_data_transaction_fee,
+            whitelisted_creators,
             royalty_paid_on_buy,
+            gate_consumed_tokens: LookupSet::new(StorageKey::GateConsumedTokens),
+            codes_by_series: LookupMap::new(StorageKey::CodesBySeries),
+            code_claim_by_token: LookupMap::new(StorageKey::CodeClaimByToken),
+            claim_links: LookupMap::new(StorageKey::ClaimLinks),
+            claim_link_reservations: LookupMap::new(StorageKey::ClaimLinkReservations),
+            voucher_public_keys: LookupMap::new(StorageKey::VoucherPublicKeys),
+            used_voucher_nonces: LookupSet::new(StorageKey::UsedVoucherNonces),
+            events_by_id: UnorderedMap::new(StorageKey::EventsById),
+            events_by_organiser: LookupMap::new(StorageKey::EventsByOrganiser),
+            series_by_event: LookupMap::new(StorageKey::SeriesByEvent),
+            venues_by_id: UnorderedMap::new(StorageKey::VenuesById),
+            venue_operators: LookupSet::new(StorageKey::VenueOperators),
+            checked_in_tokens: LookupMap::new(StorageKey::CheckedInTokens),
+            capacity_pools: UnorderedMap::new(StorageKey::CapacityPools),
+            discount_codes: LookupMap::new(StorageKey::DiscountCodes),
+            ledger_mode: false,
+            pending_balances: LookupMap::new(StorageKey::PendingBalances),
+            liabilities: Liabilities::default(),
+            creator_transaction_fees: LookupMap::new(StorageKey::CreatorTransactionFees),
+            admins: UnorderedSet::new(StorageKey::Admins),
+            creator_applications: UnorderedMap::new(StorageKey::CreatorApplications),
+            creator_profiles: LookupMap::new(StorageKey::CreatorProfiles),
+            proposed_owner_id: None,
+            timelock_operations: UnorderedMap::new(StorageKey::TimelockOperations),
+            timelock_nonce: 0,
+            secondary_fee: 0,
+            receipts_by_id: LookupMap::new(StorageKey::ReceiptsById),
+            receipts_by_token: LookupMap::new(StorageKey::ReceiptsByToken),
+            receipts_by_creator: LookupMap::new(StorageKey::ReceiptsByCreator),
+            receipts_by_series: LookupMap::new(StorageKey::ReceiptsBySeries),
+            receipts_nonce: 0,
+            sales_stats: SalesStats::default(),
+            series_sales_stats: LookupMap::new(StorageKey::SeriesSalesStats),
+            creator_sales_stats: LookupMap::new(StorageKey::CreatorSalesStats),
+            merch_redemptions: LookupMap::new(StorageKey::MerchRedemptions),
+            burned_tokens_by_series: LookupMap::new(StorageKey::BurnedTokensBySeries),
+            upgrade_credits: LookupMap::new(StorageKey::UpgradeCredits),
+            pass_expiries: LookupMap::new(StorageKey::PassExpiries),
+            renewal_deposits: LookupMap::new(StorageKey::RenewalDeposits),
         }
     }
 
@@ -167,25 +396,205 @@ impl Contract {
             "Marketplace: Owner only"
         );
 
-        assert!(
-            next_fee < 10_000,
-            "Marketplace: transaction fee is more than 10_000"
-        );
+        internal_assert_fee_below_timelock_threshold(next_fee);
+        self.internal_set_transaction_fee(next_fee, start_time);
+    }
 
-        if start_time.is_none() {
-            self.transaction_fee.current_fee = next_fee;
-            self.transaction_fee.next_fee = None;
-            self.transaction_fee.start_time = None;
-            return;
-        } else {
-            let start_time: TimestampSec = start_time.unwrap();
+    fn internal_set_transaction_fee(&mut self, next_fee: u16, start_time: Option<TimestampSec>) {
+        internal_assert_valid_transaction_fee(next_fee);
+
+        if let Some(start_time) = start_time {
             assert!(
                 start_time > to_sec(env::block_timestamp()),
                 "start_time is less than current block_timestamp"
             );
-            self.transaction_fee.next_fee = Some(next_fee);
-            self.transaction_fee.start_time = Some(start_time);
+            // Replaces a change already scheduled at start_time, later changes stay queued
+            let mut schedule = self.internal_fee_schedule();
+            schedule.retain(|scheduled| scheduled.start_time != start_time);
+            assert!(
+                schedule.len() < MAX_SCHEDULED_FEES,
+                "Marketplace: Too many scheduled fees"
+            );
+            schedule.push(ScheduledFee {
+                fee: next_fee,
+                start_time,
+            });
+            self.internal_set_fee_schedule(schedule);
+        } else {
+            self.transaction_fee.current_fee = next_fee;
+        }
+    }
+
+    #[payable]
+    pub fn queue_transaction_fee(&mut self, fee: u16, start_time: TimestampSec) {
+        assert_one_yocto();
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.tokens.owner_id,
+            "Marketplace: Owner only"
+        );
+
+        internal_assert_fee_below_timelock_threshold(fee);
+        self.internal_queue_transaction_fee(fee, start_time);
+    }
+
+    fn internal_queue_transaction_fee(&mut self, fee: u16, start_time: TimestampSec) {
+        internal_assert_valid_transaction_fee(fee);
+        assert!(
+            start_time > to_sec(env::block_timestamp()),
+            "start_time is less than current block_timestamp"
+        );
+
+        let mut schedule = self.internal_fee_schedule();
+        assert!(
+            schedule.len() < MAX_SCHEDULED_FEES,
+            "Marketplace: Too many scheduled fees"
+        );
+        assert!(
+            schedule
+                .iter()
+                .all(|scheduled| scheduled.start_time != start_time),
+            "Marketplace: Fee already scheduled at {}",
+            start_time
+        );
+
+        schedule.push(ScheduledFee { fee, start_time });
+        self.internal_set_fee_schedule(schedule);
+    }
+
+    #[payable]
+    pub fn cancel_scheduled_transaction_fee(&mut self, start_time: TimestampSec) {
+        assert_one_yocto();
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.tokens.owner_id,
+            "Marketplace: Owner only"
+        );
+
+        let mut schedule = self.internal_fee_schedule();
+        let index = schedule
+            .iter()
+            .position(|scheduled| scheduled.start_time == start_time)
+            .expect("Marketplace: No fee scheduled at that time");
+        schedule.remove(index);
+        self.internal_set_fee_schedule(schedule);
+    }
+
+    pub fn get_scheduled_transaction_fees(&self) -> Vec<ScheduledFee> {
+        self.internal_fee_schedule()
+    }
+
+    // Fee a series created or repriced at `timestamp` would lock in
+    pub fn get_transaction_fee_at(
+        &self,
+        timestamp: TimestampSec,
+        creator_id: Option<AccountId>,
+    ) -> u16 {
+        if let Some(fee) =
+            creator_id.and_then(|creator_id| self.creator_transaction_fees.get(&creator_id))
+        {
+            return fee;
+        }
+
+        self.internal_fee_schedule()
+            .iter()
+            .take_while(|scheduled| scheduled.start_time <= timestamp)
+            .last()
+            .map_or(self.transaction_fee.current_fee, |scheduled| scheduled.fee)
+    }
+
+    pub fn get_series_transaction_fee_at(
+        &self,
+        token_series_id: TokenSeriesId,
+        timestamp: TimestampSec,
+    ) -> u16 {
+        // Sales use the fee locked in when the series was created or last repriced
+        if let Some(transaction_fee) = self
+            .market_data_transaction_fee
+            .transaction_fee
+            .get(&token_series_id)
+        {
+            return transaction_fee as u16;
+        }
+
+        let token_series = self
+            .token_series_by_id
+            .get(&token_series_id)
+            .expect("Marketplace: Token series not exist");
+        self.get_transaction_fee_at(timestamp, Some(token_series.creator_id))
+    }
+
+    // Per creator fee agreements
+    #[payable]
+    pub fn set_creator_transaction_fee(&mut self, creator_id: AccountId, fee: Option<u16>) {
+        assert_one_yocto();
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.tokens.owner_id,
+            "Marketplace: Owner only"
+        );
+
+        if let Some(fee) = fee {
+            internal_assert_fee_below_timelock_threshold(fee);
+        }
+        self.internal_set_creator_transaction_fee(creator_id, fee);
+    }
+
+    fn internal_set_creator_transaction_fee(&mut self, creator_id: AccountId, fee: Option<u16>) {
+        if let Some(fee) = fee {
+            internal_assert_valid_transaction_fee(fee);
+            self.creator_transaction_fees.insert(&creator_id, &fee);
+        } else {
+            self.creator_transaction_fees.remove(&creator_id);
+        }
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"set_creator_transaction_fee",
+            "data": {
+                    "creator_id": creator_id,
+                    "fee": fee,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+    }
+
+    pub fn get_creator_transaction_fee(&self, creator_id: AccountId) -> Option<u16> {
+        self.creator_transaction_fees.get(&creator_id)
+    }
+
+    pub fn calculate_creator_transaction_fee(&mut self, creator_id: &AccountId) -> u128 {
+        let current_transaction_fee = self.calculate_current_transaction_fee();
+        self.creator_transaction_fees
+            .get(creator_id)
+            .map_or(current_transaction_fee, u128::from)
+    }
+
+    fn internal_fee_schedule(&self) -> Vec<ScheduledFee> {
+        let mut schedule = vec![];
+        if let (Some(fee), Some(start_time)) = (
+            self.transaction_fee.next_fee,
+            self.transaction_fee.start_time,
+        ) {
+            schedule.push(ScheduledFee { fee, start_time });
         }
+        schedule.extend(self.transaction_fee.queued_fees.iter().cloned());
+        schedule
+    }
+
+    fn internal_set_fee_schedule(&mut self, mut schedule: Vec<ScheduledFee>) {
+        schedule.sort_by_key(|scheduled| scheduled.start_time);
+
+        let mut schedule = schedule.into_iter();
+        let next = schedule.next();
+        self.transaction_fee.next_fee = next.as_ref().map(|scheduled| scheduled.fee);
+        self.transaction_fee.start_time = next.as_ref().map(|scheduled| scheduled.start_time);
+        self.transaction_fee.queued_fees = schedule.collect();
     }
 
     pub fn calculate_market_data_transaction_fee(
@@ -195,7 +604,7 @@ impl Contract {
         if let Some(transaction_fee) = self
             .market_data_transaction_fee
             .transaction_fee
-            .get(&token_series_id)
+            .get(token_series_id)
         {
             return transaction_fee;
         }
@@ -205,13 +614,15 @@ impl Contract {
     }
 
     pub fn calculate_current_transaction_fee(&mut self) -> u128 {
-        let transaction_fee: &TransactionFee = &self.transaction_fee;
-        if transaction_fee.next_fee.is_some() {
-            if to_sec(env::block_timestamp()) >= transaction_fee.start_time.unwrap() {
-                self.transaction_fee.current_fee = transaction_fee.next_fee.unwrap();
-                self.transaction_fee.next_fee = None;
-                self.transaction_fee.start_time = None;
-            }
+        let mut schedule = self.internal_fee_schedule();
+        let due = schedule
+            .iter()
+            .take_while(|scheduled| to_sec(env::block_timestamp()) >= scheduled.start_time)
+            .count();
+        if due > 0 {
+            self.transaction_fee.current_fee = schedule[due - 1].fee;
+            schedule.drain(..due);
+            self.internal_set_fee_schedule(schedule);
         }
         self.transaction_fee.current_fee as u128
     }
@@ -224,7 +635,7 @@ impl Contract {
         if let Some(transaction_fee) = self
             .market_data_transaction_fee
             .transaction_fee
-            .get(&token_series_id)
+            .get(token_series_id)
         {
             return transaction_fee;
         }
@@ -242,8 +653,9 @@ impl Contract {
         );
 
         assert!(
-            fee < 10_000,
-            "Marketplace: transaction fee is more than 10_000"
+            fee <= TIMELOCK_FEE_THRESHOLD as u128,
+            "Marketplace: transaction fee above {} must go through the timelock",
+            TIMELOCK_FEE_THRESHOLD
         );
 
         self.market_data_transaction_fee
@@ -251,34 +663,56 @@ impl Contract {
             .insert(token_series_id, &fee);
     }
 
-    // Whitelisting
+    // Secondary sale fee paid to the treasury through nft_payout
     #[payable]
-    pub fn add_whitelisted_creator(&mut self, creator: AccountId) {
+    pub fn set_secondary_fee(&mut self, fee: u16) {
         assert_one_yocto();
         assert_eq!(
             env::predecessor_account_id(),
             self.tokens.owner_id,
             "Marketplace: Owner only"
         );
+        internal_assert_fee_below_timelock_threshold(fee);
+
+        self.secondary_fee = fee;
+    }
+
+    pub fn get_secondary_fee(&self) -> u16 {
+        self.secondary_fee
+    }
+
+    // Whitelisting
+    #[payable]
+    pub fn add_whitelisted_creator(&mut self, creator: AccountId) {
+        assert_one_yocto();
+        self.internal_assert_admin();
         self.whitelisted_creators.insert(&creator);
     }
 
     #[payable]
     pub fn remove_whitelisted_creator(&mut self, creator: AccountId) {
         assert_one_yocto();
-        assert_eq!(
-            env::predecessor_account_id(),
-            self.tokens.owner_id,
-            "Marketplace: Owner only"
-        );
+        self.internal_assert_admin();
         self.whitelisted_creators.remove(&creator);
     }
 
-    pub fn get_whitelist(&self) -> Vec<AccountId> {
-        self.whitelisted_creators.to_vec()
+    pub fn get_whitelist(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<AccountId> {
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        assert_ne!(limit, 0, "Cannot provide limit of 0.");
+
+        self.whitelisted_creators
+            .iter()
+            .skip(start_index as usize)
+            .take(limit)
+            .collect()
+    }
+
+    pub fn get_whitelist_len(&self) -> U64 {
+        self.whitelisted_creators.len().into()
     }
 
-    // Royalty paid on buy
+    // Royalty paid on buy, default for new series
     pub fn get_royalty_paid_on_buy(&self) -> bool {
         self.royalty_paid_on_buy
     }
@@ -296,17 +730,102 @@ impl Contract {
 
     // Treasury
     #[payable]
-    pub fn set_treasury(&mut self, treasury_id: AccountId) {
-        assert_one_yocto();
+    pub fn set_treasury(&mut self, treasury_id: AccountId) -> TimelockOperation {
+        self.schedule_timelock_operation(TimelockAction::SetTreasury {
+            treasury: HashMap::from([(treasury_id, 10_000)]),
+        })
+    }
+
+    #[payable]
+    pub fn set_treasury_weights(&mut self, treasury: HashMap<AccountId, u32>) -> TimelockOperation {
+        self.schedule_timelock_operation(TimelockAction::SetTreasury { treasury })
+    }
+
+    pub fn get_treasury(&self) -> HashMap<AccountId, u32> {
+        self.treasury.clone()
+    }
+
+    // Splits an amount between treasury recipients, rounding dust goes to the last one
+    pub(crate) fn internal_treasury_split(&self, amount: Balance) -> Vec<(AccountId, Balance)> {
+        let mut recipients: Vec<(&AccountId, &u32)> = self.treasury.iter().collect();
+        recipients.sort();
+
+        let mut remaining = amount;
+        let mut split: Vec<(AccountId, Balance)> = recipients
+            .iter()
+            .map(|(account_id, weight)| {
+                let share = royalty_to_payout(**weight, amount).0;
+                remaining = remaining
+                    .checked_sub(share)
+                    .expect("Marketplace: treasury weights are more than 10_000");
+                ((*account_id).clone(), share)
+            })
+            .collect();
+        if let Some((_, share)) = split.last_mut() {
+            *share = share
+                .checked_add(remaining)
+                .expect("Marketplace: treasury split overflow");
+        }
+
+        split
+    }
+
+    // Series
+    #[payable]
+    pub fn nft_set_series_primary_split(
+        &mut self,
+        token_series_id: TokenSeriesId,
+        primary_split: Option<HashMap<AccountId, u32>>,
+        royalty_paid_on_buy: Option<bool>,
+    ) {
+        let initial_storage_usage = env::storage_usage();
+
+        let mut token_series = self
+            .token_series_by_id
+            .get(&token_series_id)
+            .expect("Marketplace: Token series not exist");
         assert_eq!(
             env::predecessor_account_id(),
-            self.tokens.owner_id,
-            "Marketplace: Owner only"
+            token_series.creator_id,
+            "Marketplace: Creator only"
+        );
+
+        let primary_split = primary_split.unwrap_or_default();
+        let total = assert_valid_split(&primary_split, "primary split");
+        assert!(
+            total <= 10_000,
+            "Marketplace: primary split is more than 10_000"
+        );
+
+        token_series.primary_split = primary_split;
+        if let Some(royalty_paid_on_buy) = royalty_paid_on_buy {
+            token_series.royalty_paid_on_buy = royalty_paid_on_buy;
+        }
+        self.token_series_by_id
+            .insert(&token_series_id, &token_series);
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"nft_set_series_primary_split",
+            "data": {
+                    "token_series_id": token_series_id,
+                    "primary_split": token_series.primary_split,
+                    "royalty_paid_on_buy": token_series.royalty_paid_on_buy,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+
+        refund_deposit(
+            env::storage_usage().saturating_sub(initial_storage_usage),
+            0,
         );
-        self.treasury_id = treasury_id;
     }
 
-    // Series
     #[payable]
     pub fn set_nft_series_sale_time(
         &mut self,
@@ -326,26 +845,23 @@ impl Contract {
             .get(token_series_id)
             .expect("Marketplace: Token series not exist");
 
-        if start_time.is_some() && end_time.is_some() {
-            assert!(
-                start_time.unwrap() > end_time.unwrap(),
-                "start_time is less than end_time"
-            );
+        if let (Some(start), Some(end)) = (start_time, end_time) {
+            assert!(start > end, "start_time is less than end_time");
 
             token_series.start_time = start_time;
             token_series.end_time = end_time;
             self.token_series_by_id
                 .insert(token_series_id, &token_series);
         } else {
-            if start_time.is_some() {
+            if let Some(start) = start_time {
                 assert!(
-                    start_time.unwrap() > to_sec(env::block_timestamp()),
+                    start > to_sec(env::block_timestamp()),
                     "start_time is less than current block_timestamp"
                 );
 
-                if token_series.end_time.is_some() {
+                if let Some(series_end) = token_series.end_time {
                     assert!(
-                        start_time.unwrap() < token_series.end_time.unwrap(),
+                        start < series_end,
                         "start_time is less than token series end_time"
                     );
                 }
@@ -353,15 +869,15 @@ impl Contract {
                 token_series.start_time = start_time;
             }
 
-            if end_time.is_some() {
+            if let Some(end) = end_time {
                 assert!(
-                    end_time.unwrap() > to_sec(env::block_timestamp()),
+                    end > to_sec(env::block_timestamp()),
                     "end_time is less than current block_timestamp"
                 );
 
-                if token_series.start_time.is_some() {
+                if let Some(series_start) = token_series.start_time {
                     assert!(
-                        end_time.unwrap() > token_series.start_time.unwrap(),
+                        end > series_start,
                         "end_time is greater than token series start_time"
                     );
                 }
@@ -406,6 +922,7 @@ impl Contract {
         royalty: Option<HashMap<AccountId, u32>>,
         start_time: Option<TimestampSec>,
         end_time: Option<TimestampSec>,
+        kind: Option<SeriesKind>,
     ) -> TokenSeriesJson {
         assert!(
             self.whitelisted_creators
@@ -417,10 +934,9 @@ impl Contract {
         let initial_storage_usage = env::storage_usage();
         let caller_id = env::predecessor_account_id();
 
-        if creator_id.is_some() {
+        if let Some(creator_id) = creator_id {
             assert_eq!(
-                creator_id.unwrap(),
-                caller_id,
+                creator_id, caller_id,
                 "Marketplace: Caller is not creator_id"
             );
         }
@@ -439,38 +955,24 @@ impl Contract {
             "Marketplace: token_metadata.title is required"
         );
 
-        let mut total_perpetual = 0;
-        let mut total_accounts = 0;
-        let royalty_res: HashMap<AccountId, u32> = if let Some(royalty) = royalty {
-            for (k, v) in royalty.iter() {
-                if !is_valid_account_id(k.as_bytes()) {
-                    env::panic_str("Not valid account_id for royalty");
-                };
-                total_perpetual += *v;
-                total_accounts += 1;
-            }
-            royalty
-        } else {
-            HashMap::new()
-        };
-
-        assert!(
-            total_accounts <= 50,
-            "Marketplace: royalty exceeds 50 accounts"
-        );
+        let royalty_res: HashMap<AccountId, u32> = royalty.unwrap_or_default();
+        let total_perpetual = assert_valid_split(&royalty_res, "royalty");
 
         assert!(
             total_perpetual <= 9000,
             "Marketplace Exceeds maximum royalty -> 9000",
         );
 
-        let price_res: Option<u128> = if price.is_some() {
+        let kind = kind.unwrap_or_default();
+        kind.assert_valid(start_time);
+
+        let price_res: Option<u128> = if let Some(price) = price {
             assert!(
-                price.unwrap().0 < MAX_PRICE,
+                price.0 < MAX_PRICE,
                 "Marketplace: price higher than {}",
                 MAX_PRICE
             );
-            Some(price.unwrap().0)
+            Some(price.0)
         } else {
             None
         };
@@ -488,23 +990,30 @@ impl Contract {
             &TokenSeries {
                 metadata: update_metadata.clone(),
                 creator_id: caller_id,
-                tokens: UnorderedSet::new(
-                    StorageKey::TokensBySeriesInner {
-                        token_series: token_series_id.clone(),
-                    }
-                    .try_to_vec()
-                    .unwrap(),
-                ),
+                tokens: UnorderedSet::new(StorageKey::TokensBySeriesInner {
+                    token_series: token_series_id.clone(),
+                }),
                 price: price_res,
                 is_mintable: true,
                 royalty: royalty_res.clone(),
                 start_time,
                 end_time,
+                gate: None,
+                event_id: None,
+                venue_id: None,
+                capacity_pool_id: None,
+                primary_split: HashMap::new(),
+                royalty_paid_on_buy: self.royalty_paid_on_buy,
+                kind: kind.clone(),
+                pass_uses: None,
+                renewal: None,
+                upgrade_paths: HashMap::new(),
             },
         );
 
         // set market data transaction fee
-        let current_transaction_fee = self.calculate_current_transaction_fee();
+        let current_transaction_fee =
+            self.calculate_creator_transaction_fee(&env::predecessor_account_id());
         self.market_data_transaction_fee
             .transaction_fee
             .insert(&token_series_id, &current_transaction_fee);
@@ -519,7 +1028,8 @@ impl Contract {
                     "creator_id": env::predecessor_account_id(),
                     "price": price,
                     "royalty": royalty_res,
-                    "transaction_fee": &current_transaction_fee.to_string()
+                    "transaction_fee": &current_transaction_fee.to_string(),
+                    "kind": kind,
             }
         })
         .to_string();
@@ -538,14 +1048,21 @@ impl Contract {
             transaction_fee: Some(current_transaction_fee.into()),
             start_time,
             end_time,
+            gate: None,
+            event_id: None,
+            venue_id: None,
+            capacity_pool_id: None,
+            primary_split: HashMap::new(),
+            royalty_paid_on_buy: self.royalty_paid_on_buy,
+            kind,
+            pass_uses: None,
+            renewal: None,
+            upgrade_paths: HashMap::new(),
         }
     }
 
     #[payable]
     pub fn nft_buy(&mut self, token_series_id: TokenSeriesId) -> Token {
-        let initial_storage_usage = env::storage_usage();
-        let attached_deposit = env::attached_deposit();
-        let receiver_id = env::predecessor_account_id();
         let token_series = self
             .token_series_by_id
             .get(&token_series_id)
@@ -553,71 +1070,158 @@ impl Contract {
 
         let price: u128 = token_series.price.expect("Marketplace: not for sale");
 
+        self.internal_buy(&token_series_id, &token_series, price)
+    }
+
+    fn internal_buy(
+        &mut self,
+        token_series_id: &TokenSeriesId,
+        token_series: &TokenSeries,
+        price: Balance,
+    ) -> Token {
+        let initial_storage_usage = env::storage_usage();
+        let attached_deposit = env::attached_deposit().as_yoctonear();
+        let receiver_id = env::predecessor_account_id();
+
         assert!(
             attached_deposit >= price,
             "Marketplace: attached deposit is less than price : {}",
             price
         );
 
+        // Check gating rule and consume gating token
+        self.internal_assert_gate(token_series_id, token_series, &receiver_id);
+
         // Mint token
         let token: Token = self._nft_mint_series(token_series_id.clone(), receiver_id);
 
+        // Pay treasury, royalties and creator
+        self.internal_pay_sale(
+            token_series_id,
+            token_series,
+            &token.token_id,
+            &token.owner_id,
+            price,
+            SaleType::Primary,
+        );
+
+        refund_deposit(env::storage_usage() - initial_storage_usage, price);
+
+        token
+    }
+
+    fn internal_pay_sale(
+        &mut self,
+        token_series_id: &TokenSeriesId,
+        token_series: &TokenSeries,
+        token_id: &TokenId,
+        buyer_id: &AccountId,
+        price: Balance,
+        sale_type: SaleType,
+    ) {
+        let transaction_fee = self.calculate_market_data_transaction_fee(token_series_id);
+        let sale_split = self.internal_sale_split(token_series, price, transaction_fee);
+
+        // Pay transaction fee to treasury
+        for (treasury_id, share) in sale_split.treasury.iter() {
+            self.internal_pay(treasury_id, *share);
+        }
+
+        // Pay venue fee to venue
+        if let Some((venue_owner_id, for_venue)) = &sale_split.venue {
+            self.internal_pay(venue_owner_id, *for_venue);
+        }
+
+        // Payout to token_series creator, the payout account may also hold a split
+        let mut payout = sale_split.split.clone();
+        *payout.entry(sale_split.seller_id.clone()).or_insert(0) += sale_split.seller_net;
+
+        // Execute payments
+        payout.iter().for_each(|(k, v)| {
+            self.internal_pay(k, *v);
+        });
+
+        self.internal_add_receipt(
+            token_id,
+            token_series,
+            buyer_id.clone(),
+            price,
+            sale_split,
+            sale_type,
+        );
+    }
+
+    // Splits a primary sale price between treasury, venue, split accounts and creator
+    pub(crate) fn internal_sale_split(
+        &self,
+        token_series: &TokenSeries,
+        price: Balance,
+        transaction_fee: u128,
+    ) -> SaleSplit {
+        self.internal_try_sale_split(token_series, price, transaction_fee)
+            .unwrap_or_else(|reason| panic!("{}", reason))
+    }
+
+    // Same as internal_sale_split, but returns why the fees don't fit in the price
+    pub(crate) fn internal_try_sale_split(
+        &self,
+        token_series: &TokenSeries,
+        price: Balance,
+        transaction_fee: u128,
+    ) -> Result<SaleSplit, String> {
+        let fees_too_high = || "Marketplace: fees are more than price".to_string();
+
         // Calculate transaction fee
-        let for_treasury = price as u128
-            * self.calculate_market_data_transaction_fee(&token_series_id)
+        let for_treasury = price
+            .checked_mul(transaction_fee)
+            .ok_or_else(fees_too_high)?
             / 10_000u128;
 
+        // Calculate venue fee
+        let venue = self.internal_venue_fee(token_series, price);
+        let for_venue = venue.as_ref().map_or(0, |(_, amount)| *amount);
+
         // Calculate leftover deposit
-        let price_deducted = price - for_treasury;
+        let price_deducted = price
+            .checked_sub(for_treasury)
+            .and_then(|price| price.checked_sub(for_venue))
+            .ok_or_else(fees_too_high)?;
 
-        // Transfer transaction fee to treasury
-        if for_treasury != 0 {
-            Promise::new(self.treasury_id.clone()).transfer(for_treasury);
-        }
+        let mut split = HashMap::new();
+        let mut total_perpetual = 0;
+
+        // Primary split takes precedence, then royalties if the series pays them on buy
+        let split_source = if !token_series.primary_split.is_empty() {
+            Some(&token_series.primary_split)
+        } else if token_series.royalty_paid_on_buy && !token_series.royalty.is_empty() {
+            Some(&token_series.royalty)
+        } else {
+            None
+        };
 
-        // Check if there are royalties to be paid and if they should be paid
-        if self.royalty_paid_on_buy && token_series.royalty.len() > 0 {
+        if let Some(split_source) = split_source {
             assert!(
-                token_series.royalty.len() as u32 <= 50,
+                split_source.len() as u32 <= 50,
                 "Market cannot payout to that many receivers"
             );
 
-            let mut payout: Payout = Payout {
-                payout: HashMap::new(),
-            };
-
-            let mut total_perpetual = 0;
-
-            // Calculate royalties
-            for (k, v) in token_series.royalty.iter() {
+            // Calculate split
+            for (k, v) in split_source.iter() {
                 // Makes sure that token_series creator is ignored for initial buy payout
                 if *k != token_series.creator_id {
-                    let key = k.clone();
-                    payout
-                        .payout
-                        .insert(key, royalty_to_payout(*v, price_deducted));
+                    split.insert(k.clone(), royalty_to_payout(*v, price_deducted).0);
                     total_perpetual += *v;
                 }
             }
-
-            // Payout to token_series creator
-            payout.payout.insert(
-                token_series.creator_id.clone(),
-                royalty_to_payout(10000 - total_perpetual, price_deducted),
-            );
-
-            // Execute payments
-            payout.payout.iter().for_each(|(k, v)| {
-                Promise::new(k.clone()).transfer(v.0);
-            });
-        } else {
-            // Pay leftover deposit to token_series creator
-            Promise::new(token_series.creator_id).transfer(price_deducted);
         }
 
-        refund_deposit(env::storage_usage() - initial_storage_usage, price);
-
-        token
+        Ok(SaleSplit {
+            treasury: self.internal_treasury_split(for_treasury),
+            venue,
+            split,
+            seller_id: self.internal_payout_id(&token_series.creator_id),
+            seller_net: royalty_to_payout(10000 - total_perpetual, price_deducted).0,
+        })
     }
 
     #[payable]
@@ -655,17 +1259,25 @@ impl Contract {
             token_series.is_mintable,
             "Marketplace: Token series is not mintable"
         );
+        assert!(
+            self.internal_is_active_creator(&token_series.creator_id),
+            "Marketplace: Creator is no longer whitelisted"
+        );
+
+        if let Some(event_id) = &token_series.event_id {
+            self.internal_assert_event_on_sale(event_id);
+        }
 
-        if token_series.start_time.is_some() {
+        if let Some(start_time) = token_series.start_time {
             assert!(
-                token_series.start_time.unwrap() < to_sec(env::block_timestamp()),
+                start_time < to_sec(env::block_timestamp()),
                 "Marketplace: Token series sale not started yet"
             );
         }
 
-        if token_series.end_time.is_some() {
+        if let Some(end_time) = token_series.end_time {
             assert!(
-                token_series.end_time.unwrap() > to_sec(env::block_timestamp()),
+                end_time > to_sec(env::block_timestamp()),
                 "Marketplace: Token series sale ended"
             );
         }
@@ -674,14 +1286,22 @@ impl Contract {
 
         if token_series.metadata.copies.is_some() {
             let max_copies = token_series.metadata.copies.unwrap_or(u64::MAX);
-            assert!(num_tokens < max_copies, "Series supply maxed");
+            // Copies held back for outstanding claim links
+            let reserved = self.internal_claim_link_reservations(&token_series_id);
+            assert!(num_tokens + reserved < max_copies, "Series supply maxed");
 
             if (num_tokens + 1) >= max_copies {
                 token_series.is_mintable = false;
                 token_series.price = None;
+            } else if (num_tokens + 1 + reserved) >= max_copies {
+                token_series.price = None;
             }
         }
 
+        if let Some(capacity_pool_id) = &token_series.capacity_pool_id {
+            self.internal_consume_capacity(capacity_pool_id);
+        }
+
         let token_id = format!("{}{}{}", &token_series_id, TOKEN_DELIMETER, num_tokens + 1);
         token_series.tokens.insert(&token_id);
         self.token_series_by_id
@@ -759,6 +1379,13 @@ impl Contract {
             minted_copies
         );
 
+        let reserved_copies = self.internal_claim_link_reservations(&token_series_id);
+        assert!(
+            (copies - decrease_copies.0) >= minted_copies + reserved_copies,
+            "Marketplace: cannot decrease supply, reserved for claim links : {}",
+            reserved_copies
+        );
+
         let is_non_mintable = if (copies - decrease_copies.0) == minted_copies {
             token_series.is_mintable = false;
             true
@@ -808,27 +1435,28 @@ impl Contract {
             "Marketplace: Creator only"
         );
 
-        assert_eq!(
-            token_series.is_mintable, true,
+        assert!(
+            token_series.is_mintable,
             "Marketplace: token series is not mintable"
         );
 
-        if price.is_none() {
-            token_series.price = None;
-        } else {
+        if let Some(price) = price {
             assert!(
-                price.unwrap().0 < MAX_PRICE,
+                price.0 < MAX_PRICE,
                 "Marketplace: price higher than {}",
                 MAX_PRICE
             );
-            token_series.price = Some(price.unwrap().0);
+            token_series.price = Some(price.0);
+        } else {
+            token_series.price = None;
         }
 
         self.token_series_by_id
             .insert(&token_series_id, &token_series);
 
         // set market data transaction fee
-        let current_transaction_fee = self.calculate_current_transaction_fee();
+        let current_transaction_fee =
+            self.calculate_creator_transaction_fee(&token_series.creator_id);
         self.market_data_transaction_fee
             .transaction_fee
             .insert(&token_series_id, &current_transaction_fee);
@@ -849,7 +1477,7 @@ impl Contract {
 
         env::log_str(&event);
 
-        return price;
+        price
     }
 
     // CUSTOM VIEWS
@@ -867,24 +1495,37 @@ impl Contract {
             transaction_fee: Some(current_transaction_fee.into()),
             start_time: token_series.start_time,
             end_time: token_series.end_time,
+            gate: token_series.gate,
+            event_id: token_series.event_id,
+            venue_id: token_series.venue_id,
+            capacity_pool_id: token_series.capacity_pool_id,
+            primary_split: token_series.primary_split,
+            royalty_paid_on_buy: token_series.royalty_paid_on_buy,
+            kind: token_series.kind,
+            pass_uses: token_series.pass_uses,
+            renewal: token_series.renewal,
+            upgrade_paths: token_series.upgrade_paths,
         }
     }
 
     pub fn nft_get_series_price(self, token_series_id: TokenSeriesId) -> Option<U128> {
         let price = self.token_series_by_id.get(&token_series_id).unwrap().price;
-        match price {
-            Some(p) => return Some(U128::from(p)),
-            None => return None,
-        };
+        price.map(U128::from)
     }
 
+    // Series keep the ids of burned tokens so token ids and copies aren't reused
     pub fn nft_supply_for_series(&self, token_series_id: TokenSeriesId) -> U64 {
-        self.token_series_by_id
+        let minted = self
+            .token_series_by_id
             .get(&token_series_id)
             .expect("Token series not exist")
             .tokens
-            .len()
-            .into()
+            .len();
+        let burned = self
+            .burned_tokens_by_series
+            .get(&token_series_id)
+            .unwrap_or(0);
+        (minted - burned).into()
     }
 
     pub fn get_current_time_seconds(&self) -> TimestampSec {
@@ -896,9 +1537,47 @@ fn royalty_to_payout(a: u32, b: Balance) -> U128 {
     U128(a as u128 * b / 10_000u128)
 }
 
+fn internal_assert_valid_transaction_fee(fee: u16) {
+    assert!(
+        fee <= MAX_TRANSACTION_FEE,
+        "Marketplace: transaction fee is more than {}",
+        MAX_TRANSACTION_FEE
+    );
+}
+
+fn internal_assert_fee_below_timelock_threshold(fee: u16) {
+    assert!(
+        fee <= TIMELOCK_FEE_THRESHOLD,
+        "Marketplace: transaction fee above {} must go through the timelock",
+        TIMELOCK_FEE_THRESHOLD
+    );
+}
+
+// Returns the total basis points of the split
+fn assert_valid_split(split: &HashMap<AccountId, u32>, name: &str) -> u64 {
+    for (k, weight) in split {
+        if !is_valid_account_id(k.as_bytes()) {
+            env::panic_str(&format!("Not valid account_id for {}", name));
+        };
+        assert!(
+            *weight <= 10_000,
+            "Marketplace: {} weight is more than 10_000",
+            name
+        );
+    }
+
+    assert!(
+        split.len() <= 50,
+        "Marketplace: {} exceeds 50 accounts",
+        name
+    );
+
+    split.values().map(|weight| u64::from(*weight)).sum()
+}
+
 fn refund_deposit(storage_used: u64, extra_spend: Balance) {
-    let required_cost = env::storage_byte_cost() * Balance::from(storage_used);
-    let attached_deposit = env::attached_deposit() - extra_spend;
+    let required_cost = env::storage_byte_cost().as_yoctonear() * Balance::from(storage_used);
+    let attached_deposit = env::attached_deposit().as_yoctonear() - extra_spend;
 
     assert!(
         required_cost <= attached_deposit,
@@ -908,7 +1587,15 @@ fn refund_deposit(storage_used: u64, extra_spend: Balance) {
 
     let refund = attached_deposit - required_cost;
     if refund > 1 {
-        Promise::new(env::predecessor_account_id()).transfer(refund);
+        Promise::new(env::predecessor_account_id()).transfer(NearToken::from_yoctonear(refund));
+    }
+}
+
+// Pays the storage released by a removal back to whoever paid for it
+fn refund_released_storage(account_id: &AccountId, storage_released: u64) {
+    let refund = env::storage_byte_cost().as_yoctonear() * Balance::from(storage_released);
+    if refund > 1 {
+        Promise::new(account_id.clone()).transfer(NearToken::from_yoctonear(refund));
     }
 }
 
@@ -916,8 +1603,63 @@ fn to_sec(timestamp: Timestamp) -> TimestampSec {
     (timestamp / 10u64.pow(9)) as u32
 }
 
-near_contract_standards::impl_non_fungible_token_approval!(Contract, tokens);
-near_contract_standards::impl_non_fungible_token_enumeration!(Contract, tokens);
+#[near_bindgen]
+impl NonFungibleTokenApproval for Contract {
+    #[payable]
+    fn nft_approve(
+        &mut self,
+        token_id: TokenId,
+        account_id: AccountId,
+        msg: Option<String>,
+    ) -> Option<Promise> {
+        self.tokens.nft_approve(token_id, account_id, msg)
+    }
+
+    #[payable]
+    fn nft_revoke(&mut self, token_id: TokenId, account_id: AccountId) {
+        self.tokens.nft_revoke(token_id, account_id)
+    }
+
+    #[payable]
+    fn nft_revoke_all(&mut self, token_id: TokenId) {
+        self.tokens.nft_revoke_all(token_id)
+    }
+
+    fn nft_is_approved(
+        &self,
+        token_id: TokenId,
+        approved_account_id: AccountId,
+        approval_id: Option<u64>,
+    ) -> bool {
+        self.tokens
+            .nft_is_approved(token_id, approved_account_id, approval_id)
+    }
+}
+
+#[near_bindgen]
+impl NonFungibleTokenEnumeration for Contract {
+    fn nft_total_supply(&self) -> U128 {
+        self.tokens.nft_total_supply()
+    }
+
+    fn nft_tokens(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<Token> {
+        self.tokens.nft_tokens(from_index, limit)
+    }
+
+    fn nft_supply_for_owner(&self, account_id: AccountId) -> U128 {
+        self.tokens.nft_supply_for_owner(account_id)
+    }
+
+    fn nft_tokens_for_owner(
+        &self,
+        account_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<Token> {
+        self.tokens
+            .nft_tokens_for_owner(account_id, from_index, limit)
+    }
+}
 
 #[near_bindgen]
 impl NonFungibleTokenMetadataProvider for Contract {
@@ -939,11 +1681,8 @@ impl NonFungibleTokenCore for Contract {
         // CUSTOM Implementation (switch token metadata for the token_series metadata)
         let mut token_id_iter = token_id.split(TOKEN_DELIMETER);
         let token_series_id = token_id_iter.next().unwrap().parse().unwrap();
-        let series_metadata = self
-            .token_series_by_id
-            .get(&token_series_id)
-            .unwrap()
-            .metadata;
+        let token_series = self.token_series_by_id.get(&token_series_id).unwrap();
+        let series_metadata = token_series.metadata;
 
         let mut token_metadata = self
             .tokens
@@ -961,10 +1700,38 @@ impl NonFungibleTokenCore for Contract {
         ));
 
         token_metadata.reference = series_metadata.reference;
-        token_metadata.media = series_metadata.media;
+        // Redeemed merchandise keeps the collectible media set on redemption
+        if !self.merch_redemptions.contains_key(&token_id) {
+            token_metadata.media = series_metadata.media;
+        }
         token_metadata.copies = series_metadata.copies;
         token_metadata.extra = series_metadata.extra;
 
+        // Multi-use passes show their remaining check-ins in extra
+        if let Some(pass_uses) = token_series.pass_uses {
+            let uses = self
+                .checked_in_tokens
+                .get(&token_id)
+                .map_or(0, |check_in| check_in.uses);
+            let remaining_uses = pass_uses.max_uses.saturating_sub(uses);
+
+            let extra = match token_metadata
+                .extra
+                .as_ref()
+                .and_then(|extra| near_sdk::serde_json::from_str(extra).ok())
+            {
+                Some(near_sdk::serde_json::Value::Object(mut extra)) => {
+                    extra.insert("remaining_uses".to_string(), json!(remaining_uses));
+                    near_sdk::serde_json::Value::Object(extra)
+                }
+                _ => match &token_metadata.extra {
+                    Some(extra) => json!({"extra": extra, "remaining_uses": remaining_uses}),
+                    None => json!({ "remaining_uses": remaining_uses }),
+                },
+            };
+            token_metadata.extra = Some(extra.to_string());
+        }
+
         Some(Token {
             token_id,
             owner_id,
@@ -1016,15 +1783,17 @@ impl NonFungibleTokenResolver for Contract {
 
 // Tests
 #[cfg(all(test, not(target_arch = "wasm32")))]
+#[allow(clippy::identity_op, clippy::redundant_field_names)]
 mod tests {
     use crate::payout::Payouts;
 
     use super::*;
+    use near_sdk::json_types::Base64VecU8;
     use near_sdk::test_utils::{accounts, VMContextBuilder};
     use near_sdk::testing_env;
 
     const STORAGE_FOR_CREATE_SERIES: Balance = 8540000000000000000000;
-    const STORAGE_FOR_MINT: Balance = 11280000000000000000000;
+    const STORAGE_FOR_MINT: Balance = 25000000000000000000000;
 
     fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
         let mut builder = VMContextBuilder::new();
@@ -1035,6 +1804,50 @@ mod tests {
         builder
     }
 
+    fn get_transfers() -> Vec<(AccountId, Balance)> {
+        near_sdk::test_utils::get_created_receipts()
+            .into_iter()
+            .flat_map(|receipt| {
+                let receiver_id = receipt.receiver_id;
+                receipt
+                    .actions
+                    .into_iter()
+                    .filter_map(move |action| match action {
+                        near_sdk::mock::MockAction::Transfer { deposit, .. } => {
+                            Some((receiver_id.clone(), deposit.as_yoctonear()))
+                        }
+                        _ => None,
+                    })
+            })
+            .collect()
+    }
+
+    fn get_function_calls() -> Vec<(AccountId, String, Vec<u8>, Balance)> {
+        near_sdk::test_utils::get_created_receipts()
+            .into_iter()
+            .flat_map(|receipt| {
+                let receiver_id = receipt.receiver_id;
+                receipt
+                    .actions
+                    .into_iter()
+                    .filter_map(move |action| match action {
+                        near_sdk::mock::MockAction::FunctionCallWeight {
+                            method_name,
+                            args,
+                            attached_deposit,
+                            ..
+                        } => Some((
+                            receiver_id.clone(),
+                            String::from_utf8(method_name).unwrap(),
+                            args,
+                            attached_deposit.as_yoctonear(),
+                        )),
+                        _ => None,
+                    })
+            })
+            .collect()
+    }
+
     fn setup_contract() -> (VMContextBuilder, Contract) {
         let mut context = VMContextBuilder::new();
         testing_env!(context.predecessor_account_id(accounts(0)).build());
@@ -1042,7 +1855,7 @@ mod tests {
 
         testing_env!(context
             .predecessor_account_id(accounts(0))
-            .attached_deposit(1)
+            .attached_deposit(NearToken::from_yoctonear(1))
             .build());
 
         contract.add_whitelisted_creator(accounts(1));
@@ -1107,6 +1920,7 @@ mod tests {
             Some(royalty.clone()),
             None,
             None,
+            None,
         );
     }
 
@@ -1116,7 +1930,7 @@ mod tests {
 
         testing_env!(context
             .predecessor_account_id(accounts(1))
-            .attached_deposit(STORAGE_FOR_CREATE_SERIES)
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
             .build());
 
         let mut royalty: HashMap<AccountId, u32> = HashMap::new();
@@ -1153,7 +1967,7 @@ mod tests {
         let (mut context, mut contract) = setup_contract();
         testing_env!(context
             .predecessor_account_id(accounts(1))
-            .attached_deposit(STORAGE_FOR_CREATE_SERIES)
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
             .build());
 
         let mut royalty: HashMap<AccountId, u32> = HashMap::new();
@@ -1168,7 +1982,9 @@ mod tests {
 
         testing_env!(context
             .predecessor_account_id(accounts(2))
-            .attached_deposit(1 * 10u128.pow(24) + STORAGE_FOR_MINT)
+            .attached_deposit(NearToken::from_yoctonear(
+                1 * 10u128.pow(24) + STORAGE_FOR_MINT
+            ))
             .build());
 
         let token = contract.nft_buy("1".to_string());
@@ -1177,67 +1993,142 @@ mod tests {
         assert_eq!(token_from_nft_token.unwrap().owner_id, accounts(2));
     }
 
-    #[test]
-    fn test_mint() {
-        let (mut context, mut contract) = setup_contract();
+    fn setup_gated_series(context: &mut VMContextBuilder, contract: &mut Contract) {
         testing_env!(context
             .predecessor_account_id(accounts(1))
-            .attached_deposit(STORAGE_FOR_CREATE_SERIES)
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
             .build());
 
-        let mut royalty: HashMap<AccountId, u32> = HashMap::new();
-        royalty.insert(accounts(1), 1000);
-
-        create_series(&mut contract, &royalty, None, None);
+        let royalty: HashMap<AccountId, u32> = HashMap::new();
+        create_series(contract, &royalty, Some(U128::from(10u128.pow(24))), None);
+        create_series(contract, &royalty, Some(U128::from(10u128.pow(24))), None);
 
         testing_env!(context
             .predecessor_account_id(accounts(1))
-            .attached_deposit(STORAGE_FOR_MINT)
+            .attached_deposit(NearToken::from_yoctonear(1))
             .build());
 
-        let token = contract.nft_mint("1".to_string(), accounts(2));
+        contract.set_nft_series_gate(
+            "2".to_string(),
+            Some(SeriesGate {
+                token_series_id: "1".to_string(),
+                count: 1,
+                one_per_token: true,
+            }),
+        );
+    }
 
-        let token_from_nft_token = contract.nft_token(token.token_id);
-        assert_eq!(token_from_nft_token.unwrap().owner_id, accounts(2))
+    #[test]
+    fn test_buy_gated_series() {
+        let (mut context, mut contract) = setup_contract();
+        setup_gated_series(&mut context, &mut contract);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(10u128.pow(24) + STORAGE_FOR_MINT))
+            .build());
+
+        let ticket = contract.nft_buy("1".to_string());
+        let merch = contract.nft_buy("2".to_string());
+
+        assert_eq!(merch.owner_id, accounts(2));
+        assert!(contract.nft_is_gate_token_consumed("2".to_string(), ticket.token_id));
+        assert_eq!(
+            contract
+                .nft_get_series_single("2".to_string())
+                .gate
+                .unwrap()
+                .token_series_id,
+            "1"
+        );
     }
 
     #[test]
-    fn test_nft_transfer() {
+    #[should_panic(expected = "Marketplace: Must hold 1 tokens of series 1")]
+    fn test_buy_gated_series_without_holding() {
         let (mut context, mut contract) = setup_contract();
+        setup_gated_series(&mut context, &mut contract);
+
         testing_env!(context
-            .predecessor_account_id(accounts(1))
-            .attached_deposit(STORAGE_FOR_CREATE_SERIES)
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(10u128.pow(24) + STORAGE_FOR_MINT))
             .build());
 
-        let mut royalty: HashMap<AccountId, u32> = HashMap::new();
-        royalty.insert(accounts(1), 1000);
+        contract.nft_buy("2".to_string());
+    }
 
-        create_series(&mut contract, &royalty, None, None);
+    #[test]
+    #[should_panic(expected = "Marketplace: All gating tokens already used")]
+    fn test_buy_gated_series_reuse_after_transfer() {
+        let (mut context, mut contract) = setup_contract();
+        setup_gated_series(&mut context, &mut contract);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(10u128.pow(24) + STORAGE_FOR_MINT))
+            .build());
+
+        let ticket = contract.nft_buy("1".to_string());
+        contract.nft_buy("2".to_string());
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        contract.nft_transfer(accounts(3), ticket.token_id, None, None);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(NearToken::from_yoctonear(10u128.pow(24) + STORAGE_FOR_MINT))
+            .build());
+
+        contract.nft_buy("2".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Marketplace: All gating tokens already used")]
+    fn test_buy_gated_series_consumes_count_per_copy() {
+        let (mut context, mut contract) = setup_contract();
+        setup_gated_series(&mut context, &mut contract);
 
         testing_env!(context
             .predecessor_account_id(accounts(1))
-            .attached_deposit(STORAGE_FOR_MINT)
+            .attached_deposit(NearToken::from_yoctonear(1))
             .build());
 
-        let token = contract.nft_mint("1".to_string(), accounts(2));
-        let token_id = &token.token_id;
+        contract.set_nft_series_gate(
+            "2".to_string(),
+            Some(SeriesGate {
+                token_series_id: "1".to_string(),
+                count: 2,
+                one_per_token: true,
+            }),
+        );
+
         testing_env!(context
             .predecessor_account_id(accounts(2))
-            .attached_deposit(1)
+            .attached_deposit(NearToken::from_yoctonear(10u128.pow(24) + STORAGE_FOR_MINT))
             .build());
 
-        contract.nft_transfer(accounts(3), token_id.to_string(), None, None);
+        let first = contract.nft_buy("1".to_string());
+        let second = contract.nft_buy("1".to_string());
+        contract.nft_buy("1".to_string());
+        contract.nft_buy("2".to_string());
 
-        let token = contract.nft_token(token_id.to_string()).unwrap();
-        assert_eq!(token.owner_id, accounts(3))
+        assert!(contract.nft_is_gate_token_consumed("2".to_string(), first.token_id));
+        assert!(contract.nft_is_gate_token_consumed("2".to_string(), second.token_id));
+
+        // One unused ticket left, short of the two the gate requires
+        contract.nft_buy("2".to_string());
     }
 
     #[test]
-    fn test_nft_transfer_payout() {
+    fn test_mint() {
         let (mut context, mut contract) = setup_contract();
         testing_env!(context
             .predecessor_account_id(accounts(1))
-            .attached_deposit(STORAGE_FOR_CREATE_SERIES)
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
             .build());
 
         let mut royalty: HashMap<AccountId, u32> = HashMap::new();
@@ -1247,152 +2138,3000 @@ mod tests {
 
         testing_env!(context
             .predecessor_account_id(accounts(1))
-            .attached_deposit(STORAGE_FOR_MINT)
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_MINT))
             .build());
 
         let token = contract.nft_mint("1".to_string(), accounts(2));
-        let token_id = &token.token_id;
 
+        let token_from_nft_token = contract.nft_token(token.token_id);
+        assert_eq!(token_from_nft_token.unwrap().owner_id, accounts(2))
+    }
+
+    fn setup_series_codes(context: &mut VMContextBuilder, contract: &mut Contract) -> TokenId {
         testing_env!(context
-            .predecessor_account_id(accounts(2))
-            .attached_deposit(1)
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
             .build());
 
-        let payout = contract.nft_transfer_payout(
-            accounts(3),
-            token_id.to_string(),
-            Some(0),
-            None,
-            U128::from(1 * 10u128.pow(24)),
-            Some(50),
-        );
+        let royalty: HashMap<AccountId, u32> = HashMap::new();
+        create_series(contract, &royalty, None, None);
 
-        let mut payout_calc: HashMap<AccountId, U128> = HashMap::new();
-        payout_calc.insert(
-            accounts(1),
-            U128::from((1000 * (1 * 10u128.pow(24))) / 10_000),
-        );
-        payout_calc.insert(
-            accounts(2),
-            U128::from((9000 * (1 * 10u128.pow(24))) / 10_000),
-        );
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_MINT))
+            .build());
 
-        assert_eq!(payout.payout, payout_calc);
+        let code_hashes: Vec<Base64VecU8> = ["salt-0code-0", "salt-1code-1"]
+            .iter()
+            .map(|preimage| env::sha256(preimage.as_bytes()).into())
+            .collect();
+        contract.nft_commit_series_codes("1".to_string(), code_hashes);
 
-        let token = contract.nft_token(token_id.to_string()).unwrap();
-        assert_eq!(token.owner_id, accounts(3))
+        contract.nft_mint("1".to_string(), accounts(2)).token_id
     }
 
     #[test]
-    fn test_change_transaction_fee_immediately() {
+    fn test_commit_series_codes_large_batch() {
         let (mut context, mut contract) = setup_contract();
+        setup_series_codes(&mut context, &mut contract);
 
         testing_env!(context
-            .predecessor_account_id(accounts(0))
-            .attached_deposit(1)
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(10u128.pow(25)))
             .build());
 
-        contract.set_transaction_fee(100, None);
+        let code_hashes: Vec<Base64VecU8> = (0..500)
+            .map(|slot| env::sha256(format!("salt-{}", slot).as_bytes()).into())
+            .collect();
+        let series_codes = contract.nft_commit_series_codes("1".to_string(), code_hashes);
+        assert_eq!(series_codes.total, U64(502));
 
-        assert_eq!(contract.get_transaction_fee().current_fee, 100);
+        let logs = near_sdk::test_utils::get_logs();
+        assert!(logs[0].contains("\"from_slot\":\"2\""));
+        assert!(logs[0].contains("\"count\":\"500\""));
+        assert!(!logs[0].contains("code_hashes"));
+    }
+
+    fn holder_public_key() -> PublicKey {
+        "ed25519:6E8sCci9badyRkXb3JoRpBj5p8C6Tw41ELDZoiihKEtp"
+            .parse()
+            .unwrap()
     }
 
     #[test]
-    fn test_change_transaction_fee_with_time() {
+    fn test_claim_and_reveal_code() {
         let (mut context, mut contract) = setup_contract();
+        let token_id = setup_series_codes(&mut context, &mut contract);
 
         testing_env!(context
-            .predecessor_account_id(accounts(0))
-            .attached_deposit(1)
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_MINT))
             .build());
 
-        assert_eq!(contract.get_transaction_fee().current_fee, 500);
-        assert_eq!(contract.get_transaction_fee().next_fee, None);
-        assert_eq!(contract.get_transaction_fee().start_time, None);
-
-        let next_fee: u16 = 100;
-        let start_time: Timestamp = 1618109122863866400;
-        let start_time_sec: TimestampSec = to_sec(start_time);
-        contract.set_transaction_fee(next_fee, Some(start_time_sec));
-
-        assert_eq!(contract.get_transaction_fee().current_fee, 500);
-        assert_eq!(contract.get_transaction_fee().next_fee, Some(next_fee));
         assert_eq!(
-            contract.get_transaction_fee().start_time,
-            Some(start_time_sec)
+            contract.nft_claim_code(token_id.clone(), holder_public_key()),
+            U64(0)
         );
 
         testing_env!(context
             .predecessor_account_id(accounts(1))
-            .block_timestamp(start_time + 1)
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_MINT))
             .build());
 
-        contract.calculate_current_transaction_fee();
-        assert_eq!(contract.get_transaction_fee().current_fee, next_fee);
-        assert_eq!(contract.get_transaction_fee().next_fee, None);
-        assert_eq!(contract.get_transaction_fee().start_time, None);
+        let encrypted_code: Base64VecU8 = vec![7u8; 48].into();
+        contract.nft_reveal_code(token_id.clone(), encrypted_code.clone());
+
+        // Neither state nor logs carry the plaintext code
+        assert!(near_sdk::test_utils::get_logs()
+            .iter()
+            .all(|log| !log.contains("code-0")));
+
+        let claim = contract.nft_code_claim(token_id).unwrap();
+        assert_eq!(claim.claimed_by, accounts(2));
+        assert_eq!(claim.public_key, holder_public_key());
+        assert_eq!(claim.encrypted_code, Some(encrypted_code));
+        assert_eq!(
+            claim.code_hash,
+            Base64VecU8::from(env::sha256("salt-0code-0".as_bytes()))
+        );
+
+        let series_codes = contract.nft_series_codes("1".to_string());
+        assert_eq!(series_codes.total, U64(2));
+        assert_eq!(series_codes.claimed, U64(1));
     }
 
     #[test]
-    fn test_transaction_fee_locked() {
+    #[should_panic(expected = "Marketplace: Code already revealed")]
+    fn test_reveal_code_twice() {
         let (mut context, mut contract) = setup_contract();
+        let token_id = setup_series_codes(&mut context, &mut contract);
 
         testing_env!(context
-            .predecessor_account_id(accounts(0))
-            .attached_deposit(1)
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_MINT))
             .build());
 
-        assert_eq!(contract.get_transaction_fee().current_fee, 500);
-        assert_eq!(contract.get_transaction_fee().next_fee, None);
-        assert_eq!(contract.get_transaction_fee().start_time, None);
+        contract.nft_claim_code(token_id.clone(), holder_public_key());
 
-        let next_fee: u16 = 100;
-        let start_time: Timestamp = 1618109122863866400;
-        let start_time_sec: TimestampSec = to_sec(start_time);
-        contract.set_transaction_fee(next_fee, Some(start_time_sec));
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_MINT))
+            .build());
 
-        let mut royalty: HashMap<AccountId, u32> = HashMap::new();
-        royalty.insert(accounts(1), 1000);
+        contract.nft_reveal_code(token_id.clone(), vec![7u8; 48].into());
+        contract.nft_reveal_code(token_id, vec![8u8; 48].into());
+    }
 
+    fn setup_claim_link(context: &mut VMContextBuilder, contract: &mut Contract) -> PublicKey {
         testing_env!(context
             .predecessor_account_id(accounts(1))
-            .attached_deposit(STORAGE_FOR_CREATE_SERIES)
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
             .build());
 
-        create_series(
-            &mut contract,
-            &royalty,
-            Some(U128::from(1 * 10u128.pow(24))),
-            None,
-        );
+        let royalty: HashMap<AccountId, u32> = HashMap::new();
+        create_series(contract, &royalty, None, None);
 
         testing_env!(context
             .predecessor_account_id(accounts(1))
-            .attached_deposit(1)
+            .attached_deposit(NearToken::from_yoctonear(10u128.pow(24)))
             .build());
 
-        contract.nft_set_series_price("1".to_string(), None);
+        let public_key: PublicKey = "ed25519:6E8sCci9badyRkXb3JoRpBj5p8C6Tw41ELDZoiihKEtp"
+            .parse()
+            .unwrap();
+        let claim_link = contract.nft_create_claim_link("1".to_string(), public_key.clone(), 100);
+        assert_eq!(claim_link.creator_id, accounts(1));
 
-        assert_eq!(contract.get_transaction_fee().current_fee, 500);
-        assert_eq!(contract.get_transaction_fee().next_fee, Some(next_fee));
-        assert_eq!(
-            contract.get_transaction_fee().start_time,
-            Some(start_time_sec)
-        );
+        public_key
+    }
+
+    #[test]
+    fn test_claim_link() {
+        let (mut context, mut contract) = setup_contract();
+        let public_key = setup_claim_link(&mut context, &mut contract);
 
         testing_env!(context
-            .predecessor_account_id(accounts(2))
-            .block_timestamp(start_time + 1)
-            .attached_deposit(1)
+            .current_account_id(accounts(0))
+            .predecessor_account_id(accounts(0))
+            .signer_account_pk(public_key.clone())
+            .attached_deposit(NearToken::from_yoctonear(0))
+            .build());
+
+        let claim_link = contract.nft_get_claim_link(public_key.clone()).unwrap();
+        let token = contract.nft_claim_link(accounts(3));
+
+        assert_eq!(token.owner_id, accounts(3));
+        assert!(contract.nft_get_claim_link(public_key).is_none());
+        assert_eq!(contract.get_solvency().liabilities.claim_link_deposits.0, 0);
+
+        // Unused deposit and allowance go back to the creator
+        let gas_cost =
+            Balance::from(env::prepaid_gas().as_gas()) * crate::claim_links::CLAIM_LINK_GAS_PRICE;
+        let (_, refund) = get_transfers()
+            .into_iter()
+            .find(|(receiver_id, _)| *receiver_id == accounts(1))
+            .unwrap();
+        assert!(
+            refund
+                > claim_link.deposit.0 - crate::claim_links::CLAIM_LINK_MINT_STORAGE
+                    + claim_link.allowance.0
+                    - gas_cost
+        );
+    }
+
+    // Claims the link for a new named account and replays the account creation callback
+    fn claim_link_new_account(
+        context: &mut VMContextBuilder,
+        contract: &mut Contract,
+        public_key: &PublicKey,
+        is_created: bool,
+        before_callback: impl FnOnce(&mut VMContextBuilder, &mut Contract),
+    ) -> Option<Token> {
+        let deposit = contract
+            .nft_get_claim_link(public_key.clone())
+            .unwrap()
+            .deposit
+            .0;
+
+        testing_env!(context
+            .current_account_id(accounts(0))
+            .predecessor_account_id(accounts(0))
+            .signer_account_pk(public_key.clone())
+            .attached_deposit(NearToken::from_yoctonear(0))
+            .build());
+
+        let new_public_key = PublicKey::try_from([vec![0u8], vec![1u8; 32]].concat()).unwrap();
+        contract.nft_claim_link_new_account("fan.testnet".parse().unwrap(), new_public_key);
+
+        let calls = get_function_calls();
+        let (linkdrop_id, _, _, amount) = calls
+            .iter()
+            .find(|(_, function_name, _, _)| function_name == "create_account")
+            .unwrap();
+        assert_eq!(linkdrop_id.as_str(), "testnet");
+        assert_eq!(
+            *amount,
+            deposit - crate::claim_links::CLAIM_LINK_MINT_STORAGE
+        );
+
+        let (_, _, args, _) = calls
+            .into_iter()
+            .find(|(_, function_name, _, _)| function_name == "on_claim_link_account_created")
+            .unwrap();
+        let args: near_sdk::serde_json::Value = near_sdk::serde_json::from_slice(&args).unwrap();
+
+        before_callback(context, contract);
+
+        testing_env!(
+            context
+                .predecessor_account_id(accounts(0))
+                .attached_deposit(NearToken::from_yoctonear(0))
+                .build(),
+            near_sdk::test_vm_config(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Successful(
+                is_created.to_string().into_bytes()
+            )],
+        );
+
+        contract.on_claim_link_account_created(
+            near_sdk::serde_json::from_value(args["public_key"].clone()).unwrap(),
+            near_sdk::serde_json::from_value(args["claim_link"].clone()).unwrap(),
+            near_sdk::serde_json::from_value(args["new_account_id"].clone()).unwrap(),
+            near_sdk::serde_json::from_value(args["freed_storage"].clone()).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_claim_link_new_account() {
+        let (mut context, mut contract) = setup_contract();
+        let public_key = setup_claim_link(&mut context, &mut contract);
+
+        let token =
+            claim_link_new_account(&mut context, &mut contract, &public_key, true, |_, _| {})
+                .unwrap();
+
+        assert_eq!(token.owner_id.as_str(), "fan.testnet");
+        assert!(contract.nft_get_claim_link(public_key).is_none());
+        assert_eq!(
+            contract.internal_claim_link_reservations(&"1".to_string()),
+            0
+        );
+    }
+
+    #[test]
+    fn test_claim_link_new_account_not_created() {
+        let (mut context, mut contract) = setup_contract();
+        let public_key = setup_claim_link(&mut context, &mut contract);
+        let claim_link_deposits = contract.get_solvency().liabilities.claim_link_deposits;
+
+        let token =
+            claim_link_new_account(&mut context, &mut contract, &public_key, false, |_, _| {});
+        assert!(token.is_none());
+
+        // Restored only once the linkdrop refund has landed
+        assert!(contract.nft_get_claim_link(public_key.clone()).is_none());
+        assert_eq!(contract.get_solvency().liabilities.claim_link_deposits.0, 0);
+
+        let (_, _, args, _) = get_function_calls()
+            .into_iter()
+            .find(|(_, function_name, _, _)| function_name == "on_claim_link_refunded")
+            .unwrap();
+        let args: near_sdk::serde_json::Value = near_sdk::serde_json::from_slice(&args).unwrap();
+        contract.on_claim_link_refunded(
+            near_sdk::serde_json::from_value(args["public_key"].clone()).unwrap(),
+            near_sdk::serde_json::from_value(args["claim_link"].clone()).unwrap(),
+        );
+
+        // The failed claim was paid from the key allowance
+        let gas_cost =
+            Balance::from(env::prepaid_gas().as_gas()) * crate::claim_links::CLAIM_LINK_GAS_PRICE;
+        assert!(contract.nft_get_claim_link(public_key).is_some());
+        assert_eq!(
+            contract.get_solvency().liabilities.claim_link_deposits.0,
+            claim_link_deposits.0 - gas_cost
+        );
+        assert_eq!(
+            contract.internal_claim_link_reservations(&"1".to_string()),
+            1
+        );
+    }
+
+    #[test]
+    fn test_claim_link_new_account_refunds_when_mint_fails() {
+        let (mut context, mut contract) = setup_contract();
+        let public_key = setup_claim_link(&mut context, &mut contract);
+
+        let token = claim_link_new_account(
+            &mut context,
+            &mut contract,
+            &public_key,
+            true,
+            |context, contract| {
+                testing_env!(context
+                    .predecessor_account_id(accounts(0))
+                    .attached_deposit(NearToken::from_yoctonear(1))
+                    .build());
+                contract.remove_whitelisted_creator(accounts(1));
+            },
+        );
+
+        assert!(token.is_none());
+        assert!(contract.nft_get_claim_link(public_key).is_none());
+        assert_eq!(
+            contract.internal_claim_link_reservations(&"1".to_string()),
+            0
+        );
+        assert!(get_transfers()
+            .iter()
+            .any(|(account_id, amount)| *account_id == accounts(1)
+                && *amount >= crate::claim_links::CLAIM_LINK_MINT_STORAGE));
+        assert!(near_sdk::test_utils::get_created_receipts()
+            .iter()
+            .any(|receipt| receipt
+                .actions
+                .iter()
+                .any(|action| matches!(action, near_sdk::mock::MockAction::DeleteKey { .. }))));
+    }
+
+    #[test]
+    #[should_panic(expected = "Marketplace: Creator is no longer whitelisted")]
+    fn test_claim_link_new_account_not_mintable() {
+        let (mut context, mut contract) = setup_contract();
+        let public_key = setup_claim_link(&mut context, &mut contract);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+        contract.remove_whitelisted_creator(accounts(1));
+
+        claim_link_new_account(&mut context, &mut contract, &public_key, true, |_, _| {});
+    }
+
+    #[test]
+    #[should_panic(expected = "Marketplace: Claim link not expired yet")]
+    fn test_reclaim_claim_link_before_expiry() {
+        let (mut context, mut contract) = setup_contract();
+        let public_key = setup_claim_link(&mut context, &mut contract);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        contract.nft_reclaim_claim_link(public_key);
+    }
+
+    #[test]
+    fn test_reclaim_claim_link_after_expiry() {
+        let (mut context, mut contract) = setup_contract();
+        let public_key = setup_claim_link(&mut context, &mut contract);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(100 * 10u64.pow(9))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        let claim_link = contract.nft_get_claim_link(public_key.clone()).unwrap();
+        contract.nft_reclaim_claim_link(public_key.clone());
+
+        assert!(contract.nft_get_claim_link(public_key).is_none());
+        assert_eq!(contract.get_solvency().liabilities.claim_link_deposits.0, 0);
+        let (_, refund) = get_transfers()
+            .into_iter()
+            .find(|(receiver_id, _)| *receiver_id == accounts(1))
+            .unwrap();
+        assert!(refund > claim_link.deposit.0 + crate::claim_links::CLAIM_LINK_ALLOWANCE);
+    }
+
+    fn voucher_secret_key() -> ed25519_dalek::SecretKey {
+        ed25519_dalek::SecretKey::from_bytes(&[7u8; 32]).unwrap()
+    }
+
+    fn sign_voucher(voucher: &MintVoucher) -> Base64VecU8 {
+        let secret_key = voucher_secret_key();
+        let public_key = ed25519_dalek::PublicKey::from(&secret_key);
+        let message =
+            near_sdk::borsh::to_vec(&(env::current_account_id(), voucher.clone())).unwrap();
+        ed25519_dalek::ExpandedSecretKey::from(&secret_key)
+            .sign(&message, &public_key)
+            .to_bytes()
+            .to_vec()
+            .into()
+    }
+
+    fn setup_voucher_series(
+        context: &mut VMContextBuilder,
+        contract: &mut Contract,
+    ) -> MintVoucher {
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        let royalty: HashMap<AccountId, u32> = HashMap::new();
+        create_series(contract, &royalty, None, None);
+
+        let mut public_key = vec![0u8];
+        public_key.extend(ed25519_dalek::PublicKey::from(&voucher_secret_key()).as_bytes());
+        contract.set_voucher_public_key(Some(PublicKey::try_from(public_key).unwrap()));
+
+        MintVoucher {
+            token_series_id: "1".to_string(),
+            receiver_id: accounts(3),
+            price: U128(10u128.pow(24)),
+            nonce: U64(1),
+            expires_at: 100,
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Marketplace: Voucher already redeemed")]
+    fn test_redeem_voucher_replay() {
+        let (mut context, mut contract) = setup_contract();
+        let voucher = setup_voucher_series(&mut context, &mut contract);
+        let signature = sign_voucher(&voucher);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(10u128.pow(24) + STORAGE_FOR_MINT))
+            .build());
+
+        let token = contract.nft_redeem_voucher(voucher.clone(), signature.clone());
+        assert_eq!(token.owner_id, accounts(3));
+        assert!(contract.is_voucher_nonce_used(accounts(1), U64(1)));
+
+        contract.nft_redeem_voucher(voucher, signature);
+    }
+
+    #[test]
+    #[should_panic(expected = "Marketplace: Voucher expired")]
+    fn test_redeem_voucher_expired() {
+        let (mut context, mut contract) = setup_contract();
+        let voucher = setup_voucher_series(&mut context, &mut contract);
+        let signature = sign_voucher(&voucher);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .block_timestamp(100 * 10u64.pow(9))
+            .attached_deposit(NearToken::from_yoctonear(10u128.pow(24) + STORAGE_FOR_MINT))
+            .build());
+
+        contract.nft_redeem_voucher(voucher, signature);
+    }
+
+    #[test]
+    #[should_panic(expected = "Marketplace: Invalid voucher signature")]
+    fn test_redeem_voucher_tampered() {
+        let (mut context, mut contract) = setup_contract();
+        let mut voucher = setup_voucher_series(&mut context, &mut contract);
+        let signature = sign_voucher(&voucher);
+        voucher.price = U128(0);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_MINT))
+            .build());
+
+        contract.nft_redeem_voucher(voucher, signature);
+    }
+
+    fn setup_event_series(context: &mut VMContextBuilder, contract: &mut Contract) -> EventId {
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        let royalty: HashMap<AccountId, u32> = HashMap::new();
+        create_series(contract, &royalty, Some(U128::from(10u128.pow(24))), None);
+        create_series(contract, &royalty, Some(U128::from(10u128.pow(24))), None);
+
+        let event = contract.create_event("Show".to_string(), None, 100, 200);
+        contract.nft_set_series_event("1".to_string(), Some(event.event_id.clone()));
+        contract.nft_set_series_event("2".to_string(), Some(event.event_id.clone()));
+
+        event.event_id
+    }
+
+    #[test]
+    fn test_event_registry() {
+        let (mut context, mut contract) = setup_contract();
+        let event_id = setup_event_series(&mut context, &mut contract);
+
+        let events = contract.get_events_by_organiser(accounts(1), None, None);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].status, EventStatus::Scheduled);
+
+        let event_series = contract.get_series_by_event(event_id.clone(), None, None);
+        assert_eq!(event_series.len(), 2);
+        assert_eq!(event_series[1].event_id, Some(event_id.clone()));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        contract.nft_set_series_event("2".to_string(), None);
+        assert_eq!(contract.get_series_by_event(event_id, None, None).len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Marketplace: Event is Cancelled")]
+    fn test_buy_cancelled_event_series() {
+        let (mut context, mut contract) = setup_contract();
+        let event_id = setup_event_series(&mut context, &mut contract);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        contract.set_event_status(event_id, EventStatus::Cancelled);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(10u128.pow(24) + STORAGE_FOR_MINT))
+            .build());
+
+        contract.nft_buy("1".to_string());
+    }
+
+    fn setup_venue_series(context: &mut VMContextBuilder, contract: &mut Contract) -> TokenId {
+        setup_venue_series_with_uses(context, contract, None)
+    }
+
+    fn setup_venue_series_with_uses(
+        context: &mut VMContextBuilder,
+        contract: &mut Contract,
+        pass_uses: Option<PassUses>,
+    ) -> TokenId {
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        let venue = contract.create_venue("Hall".to_string(), accounts(3), Some(1000));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        contract.add_venue_operator(venue.venue_id.clone(), accounts(5));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        let royalty: HashMap<AccountId, u32> = HashMap::new();
+        create_series(contract, &royalty, Some(U128::from(10u128.pow(24))), None);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        contract.nft_set_series_venue("1".to_string(), Some(venue.venue_id));
+        if pass_uses.is_some() {
+            testing_env!(context
+                .predecessor_account_id(accounts(1))
+                .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+                .build());
+
+            contract.nft_set_series_pass_uses("1".to_string(), pass_uses);
+        }
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(10u128.pow(24) + STORAGE_FOR_MINT))
+            .build());
+
+        contract.nft_buy("1".to_string()).token_id
+    }
+
+    #[test]
+    fn test_buy_venue_series_pays_venue_fee() {
+        let (mut context, mut contract) = setup_contract();
+        setup_venue_series(&mut context, &mut contract);
+
+        let transfers = get_transfers();
+
+        assert!(transfers.contains(&(accounts(4), 5 * 10u128.pow(22))));
+        assert!(transfers.contains(&(accounts(3), 10u128.pow(23))));
+        assert!(transfers.contains(&(accounts(1), 85 * 10u128.pow(22))));
+    }
+
+    #[test]
+    fn test_check_in() {
+        let (mut context, mut contract) = setup_contract();
+        let token_id = setup_venue_series(&mut context, &mut contract);
+
+        testing_env!(context.predecessor_account_id(accounts(5)).build());
+
+        let check_in = contract.nft_check_in(token_id.clone());
+        assert_eq!(check_in.owner_id, accounts(2));
+        assert_eq!(check_in.operator_id, accounts(5));
+        assert!(contract.nft_check_in_status(token_id).is_some());
+    }
+
+    #[test]
+    #[should_panic(expected = "Marketplace: Token already checked in")]
+    fn test_check_in_twice() {
+        let (mut context, mut contract) = setup_contract();
+        let token_id = setup_venue_series(&mut context, &mut contract);
+
+        testing_env!(context.predecessor_account_id(accounts(5)).build());
+
+        contract.nft_check_in(token_id.clone());
+        contract.nft_check_in(token_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Marketplace: venue fee is more than 5000")]
+    fn test_set_venue_fee_leaves_room_for_transaction_fee() {
+        let (mut context, mut contract) = setup_contract();
+        setup_venue_series(&mut context, &mut contract);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        contract.set_venue_fee("1".to_string(), Some(9_000));
+    }
+
+    #[test]
+    #[should_panic(expected = "Marketplace: venue fee is more than 5000")]
+    fn test_set_venue_fee_does_not_overflow() {
+        let (mut context, mut contract) = setup_contract();
+        setup_venue_series(&mut context, &mut contract);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        contract.set_venue_fee("1".to_string(), Some(u16::MAX));
+    }
+
+    #[test]
+    #[should_panic(expected = "Must attach")]
+    fn test_check_in_without_storage_deposit() {
+        let (mut context, mut contract) = setup_contract();
+        let token_id = setup_venue_series(&mut context, &mut contract);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(5))
+            .attached_deposit(NearToken::from_yoctonear(0))
+            .build());
+
+        contract.nft_check_in(token_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Marketplace: Venue operator only")]
+    fn test_check_in_not_operator() {
+        let (mut context, mut contract) = setup_contract();
+        let token_id = setup_venue_series(&mut context, &mut contract);
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+
+        contract.nft_check_in(token_id);
+    }
+
+    fn setup_capacity_pool(context: &mut VMContextBuilder, contract: &mut Contract) {
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        let royalty: HashMap<AccountId, u32> = HashMap::new();
+        create_series(
+            contract,
+            &royalty,
+            Some(U128::from(10u128.pow(24))),
+            Some(5),
+        );
+        create_series(contract, &royalty, Some(U128::from(10u128.pow(24))), None);
+
+        let capacity_pool = contract.create_capacity_pool(U64(3));
+        contract.nft_set_series_capacity_pool(
+            "1".to_string(),
+            Some(capacity_pool.capacity_pool_id.clone()),
+        );
+        contract
+            .nft_set_series_capacity_pool("2".to_string(), Some(capacity_pool.capacity_pool_id));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(10u128.pow(24) + STORAGE_FOR_MINT))
+            .build());
+
+        contract.nft_buy("1".to_string());
+        contract.nft_buy("2".to_string());
+    }
+
+    #[test]
+    fn test_capacity_pool() {
+        let (mut context, mut contract) = setup_contract();
+        setup_capacity_pool(&mut context, &mut contract);
+
+        let capacity_pool = contract.get_capacity_pool("1".to_string()).unwrap();
+        assert_eq!(capacity_pool.minted, U64(2));
+        assert_eq!(capacity_pool.remaining, U64(1));
+        assert_eq!(capacity_pool.series.len(), 2);
+
+        // Series 1 still has 4 copies left but the pool only 1
+        assert_eq!(
+            contract.nft_series_remaining_capacity("1".to_string()),
+            Some(U64(1))
+        );
+        assert_eq!(
+            contract.nft_series_remaining_capacity("2".to_string()),
+            Some(U64(1))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Marketplace: Capacity pool is full")]
+    fn test_buy_capacity_pool_full() {
+        let (mut context, mut contract) = setup_contract();
+        setup_capacity_pool(&mut context, &mut contract);
+
+        contract.nft_buy("2".to_string());
+        contract.nft_buy("1".to_string());
+    }
+
+    fn setup_discount_code(context: &mut VMContextBuilder, contract: &mut Contract) {
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        let royalty: HashMap<AccountId, u32> = HashMap::new();
+        create_series(contract, &royalty, Some(U128::from(10u128.pow(24))), None);
+
+        contract.nft_add_discount_code(
+            "1".to_string(),
+            Base64VecU8(env::sha256("BAND20".as_bytes())),
+            Discount::Percent(2000),
+            Some(1),
+            None,
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(10u128.pow(24) + STORAGE_FOR_MINT))
+            .build());
+
+        contract.nft_buy_with_code("1".to_string(), "BAND20".to_string());
+    }
+
+    #[test]
+    fn test_buy_with_discount_code() {
+        let (mut context, mut contract) = setup_contract();
+        setup_discount_code(&mut context, &mut contract);
+
+        let transfers = get_transfers();
+
+        assert!(transfers.contains(&(accounts(4), 4 * 10u128.pow(22))));
+        assert!(transfers.contains(&(accounts(1), 76 * 10u128.pow(22))));
+
+        let discount_code = contract
+            .nft_get_discount_code(
+                "1".to_string(),
+                Base64VecU8(env::sha256("BAND20".as_bytes())),
+            )
+            .unwrap();
+        assert_eq!(discount_code.uses, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Marketplace: Discount code used up")]
+    fn test_buy_with_discount_code_used_up() {
+        let (mut context, mut contract) = setup_contract();
+        setup_discount_code(&mut context, &mut contract);
+
+        contract.nft_buy_with_code("1".to_string(), "BAND20".to_string());
+    }
+
+    fn setup_ledger_sale(context: &mut VMContextBuilder, contract: &mut Contract) {
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        contract.set_ledger_mode(true);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        let royalty: HashMap<AccountId, u32> = HashMap::new();
+        create_series(contract, &royalty, Some(U128::from(10u128.pow(24))), None);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(10u128.pow(24) + STORAGE_FOR_MINT))
+            .build());
+
+        contract.nft_buy("1".to_string());
+    }
+
+    #[test]
+    fn test_buy_in_ledger_mode() {
+        let (mut context, mut contract) = setup_contract();
+        setup_ledger_sale(&mut context, &mut contract);
+
+        assert!(!get_transfers()
+            .iter()
+            .any(|(account_id, _)| *account_id == accounts(1) || *account_id == accounts(4)));
+        assert_eq!(
+            contract.get_pending_balance(accounts(4)),
+            U128(5 * 10u128.pow(22))
+        );
+        assert_eq!(
+            contract.get_pending_balance(accounts(1)),
+            U128(95 * 10u128.pow(22))
+        );
+    }
+
+    #[test]
+    fn test_withdraw() {
+        let (mut context, mut contract) = setup_contract();
+        setup_ledger_sale(&mut context, &mut contract);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        contract.withdraw(None);
+        assert_eq!(contract.get_pending_balance(accounts(1)), U128(0));
+        assert!(get_transfers().contains(&(accounts(1), 95 * 10u128.pow(22))));
+
+        // Failed transfer is credited back
+        testing_env!(
+            context
+                .predecessor_account_id(env::current_account_id())
+                .attached_deposit(NearToken::from_yoctonear(0))
+                .build(),
+            near_sdk::test_vm_config(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Failed],
+        );
+
+        contract.on_withdraw(accounts(1), U128(95 * 10u128.pow(22)));
+        assert_eq!(
+            contract.get_pending_balance(accounts(1)),
+            U128(95 * 10u128.pow(22))
+        );
+    }
+
+    // Carries the contract balance over from the previous call like the runtime does
+    fn call_with_balance<T>(
+        context: &mut VMContextBuilder,
+        balance: &mut Balance,
+        predecessor: AccountId,
+        deposit: Balance,
+        call: impl FnOnce() -> T,
+    ) -> T {
+        testing_env!(context
+            .predecessor_account_id(predecessor)
+            .attached_deposit(NearToken::from_yoctonear(deposit))
+            .account_balance(NearToken::from_yoctonear(*balance))
+            .build());
+
+        let result = call();
+        *balance = env::account_balance().as_yoctonear();
+        result
+    }
+
+    #[test]
+    fn test_solvency() {
+        let (mut context, mut contract) = setup_contract();
+        let mut balance =
+            env::storage_byte_cost().as_yoctonear() * Balance::from(env::storage_usage());
+
+        call_with_balance(&mut context, &mut balance, accounts(0), 1, || {
+            contract.set_ledger_mode(true)
+        });
+        assert_eq!(contract.get_solvency().deficit, U128(0));
+
+        let mut royalty: HashMap<AccountId, u32> = HashMap::new();
+        royalty.insert(accounts(3), 1000);
+        call_with_balance(
+            &mut context,
+            &mut balance,
+            accounts(1),
+            STORAGE_FOR_CREATE_SERIES,
+            || {
+                create_series(
+                    &mut contract,
+                    &royalty,
+                    Some(U128::from(10u128.pow(24))),
+                    None,
+                )
+            },
+        );
+        assert_eq!(contract.get_solvency().deficit, U128(0));
+
+        call_with_balance(
+            &mut context,
+            &mut balance,
+            accounts(1),
+            STORAGE_FOR_MINT,
+            || contract.nft_mint("1".to_string(), accounts(2)),
+        );
+        assert_eq!(contract.get_solvency().deficit, U128(0));
+
+        // Overpaying gets the excess refunded
+        let token = call_with_balance(
+            &mut context,
+            &mut balance,
+            accounts(2),
+            2 * 10u128.pow(24),
+            || contract.nft_buy("1".to_string()),
+        );
+        let solvency = contract.get_solvency();
+        assert_eq!(solvency.total_liabilities, U128(10u128.pow(24)));
+        assert_eq!(solvency.deficit, U128(0));
+
+        call_with_balance(&mut context, &mut balance, accounts(2), 1, || {
+            contract.nft_transfer_payout(
+                accounts(5),
+                token.token_id,
+                None,
+                None,
+                U128::from(10u128.pow(24)),
+                Some(50),
+            )
+        });
+        assert_eq!(contract.get_solvency().deficit, U128(0));
+
+        call_with_balance(&mut context, &mut balance, accounts(1), 1, || {
+            contract.withdraw(None)
+        });
+        let solvency = contract.get_solvency();
+        assert_eq!(
+            solvency.total_liabilities,
+            U128(5 * 10u128.pow(22) + 95 * 10u128.pow(21))
+        );
+        assert_eq!(solvency.deficit, U128(0));
+
+        let public_key: PublicKey = "ed25519:6E8sCci9badyRkXb3JoRpBj5p8C6Tw41ELDZoiihKEtp"
+            .parse()
+            .unwrap();
+        call_with_balance(
+            &mut context,
+            &mut balance,
+            accounts(1),
+            10u128.pow(24),
+            || contract.nft_create_claim_link("1".to_string(), public_key.clone(), 100),
+        );
+        assert_eq!(contract.get_solvency().deficit, U128(0));
+
+        context.block_timestamp(100 * 10u64.pow(9));
+        call_with_balance(&mut context, &mut balance, accounts(1), 1, || {
+            contract.nft_reclaim_claim_link(public_key)
+        });
+        let solvency = contract.get_solvency();
+        assert_eq!(solvency.liabilities.claim_link_deposits, U128(0));
+        assert_eq!(solvency.deficit, U128(0));
+    }
+
+    #[test]
+    fn test_nft_transfer() {
+        let (mut context, mut contract) = setup_contract();
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        let mut royalty: HashMap<AccountId, u32> = HashMap::new();
+        royalty.insert(accounts(1), 1000);
+
+        create_series(&mut contract, &royalty, None, None);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_MINT))
+            .build());
+
+        let token = contract.nft_mint("1".to_string(), accounts(2));
+        let token_id = &token.token_id;
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        contract.nft_transfer(accounts(3), token_id.to_string(), None, None);
+
+        let token = contract.nft_token(token_id.to_string()).unwrap();
+        assert_eq!(token.owner_id, accounts(3))
+    }
+
+    #[test]
+    fn test_nft_transfer_payout() {
+        let (mut context, mut contract) = setup_contract();
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        let mut royalty: HashMap<AccountId, u32> = HashMap::new();
+        royalty.insert(accounts(1), 1000);
+
+        create_series(&mut contract, &royalty, None, None);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_MINT))
+            .build());
+
+        let token = contract.nft_mint("1".to_string(), accounts(2));
+        let token_id = &token.token_id;
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        let payout = contract.nft_transfer_payout(
+            accounts(3),
+            token_id.to_string(),
+            Some(0),
+            None,
+            U128::from(1 * 10u128.pow(24)),
+            Some(50),
+        );
+
+        let mut payout_calc: HashMap<AccountId, U128> = HashMap::new();
+        payout_calc.insert(
+            accounts(1),
+            U128::from((1000 * (1 * 10u128.pow(24))) / 10_000),
+        );
+        payout_calc.insert(
+            accounts(2),
+            U128::from((9000 * (1 * 10u128.pow(24))) / 10_000),
+        );
+
+        assert_eq!(payout.payout, payout_calc);
+
+        let token = contract.nft_token(token_id.to_string()).unwrap();
+        assert_eq!(token.owner_id, accounts(3))
+    }
+
+    #[test]
+    fn test_change_transaction_fee_immediately() {
+        let (mut context, mut contract) = setup_contract();
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        contract.set_transaction_fee(100, None);
+
+        assert_eq!(contract.get_transaction_fee().current_fee, 100);
+    }
+
+    #[test]
+    fn test_change_transaction_fee_with_time() {
+        let (mut context, mut contract) = setup_contract();
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        assert_eq!(contract.get_transaction_fee().current_fee, 500);
+        assert_eq!(contract.get_transaction_fee().next_fee, None);
+        assert_eq!(contract.get_transaction_fee().start_time, None);
+
+        let next_fee: u16 = 100;
+        let start_time: Timestamp = 1618109122863866400;
+        let start_time_sec: TimestampSec = to_sec(start_time);
+        contract.set_transaction_fee(next_fee, Some(start_time_sec));
+
+        assert_eq!(contract.get_transaction_fee().current_fee, 500);
+        assert_eq!(contract.get_transaction_fee().next_fee, Some(next_fee));
+        assert_eq!(
+            contract.get_transaction_fee().start_time,
+            Some(start_time_sec)
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(start_time + 1)
+            .build());
+
+        contract.calculate_current_transaction_fee();
+        assert_eq!(contract.get_transaction_fee().current_fee, next_fee);
+        assert_eq!(contract.get_transaction_fee().next_fee, None);
+        assert_eq!(contract.get_transaction_fee().start_time, None);
+    }
+
+    #[test]
+    fn test_transaction_fee_locked() {
+        let (mut context, mut contract) = setup_contract();
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        assert_eq!(contract.get_transaction_fee().current_fee, 500);
+        assert_eq!(contract.get_transaction_fee().next_fee, None);
+        assert_eq!(contract.get_transaction_fee().start_time, None);
+
+        let next_fee: u16 = 100;
+        let start_time: Timestamp = 1618109122863866400;
+        let start_time_sec: TimestampSec = to_sec(start_time);
+        contract.set_transaction_fee(next_fee, Some(start_time_sec));
+
+        let mut royalty: HashMap<AccountId, u32> = HashMap::new();
+        royalty.insert(accounts(1), 1000);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        create_series(
+            &mut contract,
+            &royalty,
+            Some(U128::from(1 * 10u128.pow(24))),
+            None,
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        contract.nft_set_series_price("1".to_string(), None);
+
+        assert_eq!(contract.get_transaction_fee().current_fee, 500);
+        assert_eq!(contract.get_transaction_fee().next_fee, Some(next_fee));
+        assert_eq!(
+            contract.get_transaction_fee().start_time,
+            Some(start_time_sec)
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .block_timestamp(start_time + 1)
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        contract.calculate_current_transaction_fee();
+        assert_eq!(contract.get_transaction_fee().current_fee, next_fee);
+        assert_eq!(contract.get_transaction_fee().next_fee, None);
+        assert_eq!(contract.get_transaction_fee().start_time, None);
+
+        let series = contract.nft_get_series_single("1".to_string());
+        let series_transaction_fee: u128 = series.transaction_fee.unwrap().into();
+        assert_eq!(series_transaction_fee, 500);
+    }
+
+    #[test]
+    fn test_transaction_fee_queue() {
+        let (mut context, mut contract) = setup_contract();
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        contract.queue_transaction_fee(300, 200);
+        contract.queue_transaction_fee(100, 100);
+        contract.queue_transaction_fee(400, 300);
+        contract.cancel_scheduled_transaction_fee(300);
+
+        assert_eq!(contract.get_transaction_fee().next_fee, Some(100));
+        assert_eq!(contract.get_scheduled_transaction_fees().len(), 2);
+        assert_eq!(contract.get_transaction_fee_at(50, None), 500);
+        assert_eq!(contract.get_transaction_fee_at(150, None), 100);
+        assert_eq!(contract.get_transaction_fee_at(250, None), 300);
+
+        testing_env!(context.block_timestamp(250 * 10u64.pow(9)).build());
+
+        assert_eq!(contract.calculate_current_transaction_fee(), 300);
+        assert_eq!(contract.get_transaction_fee().next_fee, None);
+        assert!(contract.get_scheduled_transaction_fees().is_empty());
+    }
+
+    #[test]
+    fn test_set_transaction_fee_keeps_queue() {
+        let (mut context, mut contract) = setup_contract();
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        contract.queue_transaction_fee(300, 200);
+        contract.queue_transaction_fee(100, 100);
+        contract.set_transaction_fee(200, None);
+        contract.set_transaction_fee(250, Some(200));
+        contract.set_transaction_fee(150, Some(300));
+
+        assert_eq!(contract.get_transaction_fee().current_fee, 200);
+        assert_eq!(contract.get_scheduled_transaction_fees().len(), 3);
+        assert_eq!(contract.get_transaction_fee_at(150, None), 100);
+        assert_eq!(contract.get_transaction_fee_at(250, None), 250);
+        assert_eq!(contract.get_transaction_fee_at(350, None), 150);
+    }
+
+    #[test]
+    fn test_creator_transaction_fee() {
+        let (mut context, mut contract) = setup_contract();
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        contract.set_creator_transaction_fee(accounts(1), Some(250));
+        contract.queue_transaction_fee(100, 100);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        let royalty: HashMap<AccountId, u32> = HashMap::new();
+        create_series(
+            &mut contract,
+            &royalty,
+            Some(U128::from(10u128.pow(24))),
+            None,
+        );
+
+        assert_eq!(
+            contract.get_market_data_transaction_fee(&"1".to_string()),
+            250
+        );
+        assert_eq!(
+            contract.get_series_transaction_fee_at("1".to_string(), 150),
+            250
+        );
+        assert_eq!(contract.get_transaction_fee_at(150, Some(accounts(1))), 250);
+        assert_eq!(contract.get_transaction_fee_at(150, Some(accounts(2))), 100);
+    }
+
+    #[test]
+    fn test_timelock_creator_transaction_fee_above_threshold() {
+        let (mut context, mut contract) = setup_contract();
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        let operation =
+            contract.schedule_timelock_operation(TimelockAction::SetCreatorTransactionFee {
+                creator_id: accounts(1),
+                fee: Some(2_000),
+            });
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .block_timestamp(operation.executable_at as u64 * 10u64.pow(9))
+            .attached_deposit(NearToken::from_yoctonear(0))
+            .build());
+
+        contract.execute_timelock_operation(operation.operation_id);
+        assert_eq!(
+            contract.get_creator_transaction_fee(accounts(1)),
+            Some(2_000)
+        );
+    }
+
+    fn setup_creator_application(context: &mut VMContextBuilder, contract: &mut Contract) {
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        let operation = contract.add_admin(accounts(5));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .block_timestamp(operation.executable_at as u64 * 10u64.pow(9))
+            .attached_deposit(NearToken::from_yoctonear(0))
+            .build());
+
+        contract.execute_timelock_operation(operation.operation_id);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        contract.submit_creator_application("Band".to_string(), Some(accounts(3)), None);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(5))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        contract.approve_creator_application(accounts(2), true);
+    }
+
+    #[test]
+    fn test_creator_application() {
+        let (mut context, mut contract) = setup_contract();
+        setup_creator_application(&mut context, &mut contract);
+
+        assert!(contract.get_creator_application(accounts(2)).is_none());
+        assert_eq!(
+            contract.get_whitelist(Some(U128(1)), Some(1)),
+            vec![accounts(2)]
+        );
+        assert_eq!(contract.get_whitelist_len(), U64(2));
+
+        let profile = contract.get_creator_profile(accounts(2)).unwrap();
+        assert_eq!(profile.payout_id, Some(accounts(3)));
+        assert!(profile.verified);
+
+        // Proceeds go to the payout account
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        let royalty: HashMap<AccountId, u32> = HashMap::new();
+        create_series(
+            &mut contract,
+            &royalty,
+            Some(U128::from(10u128.pow(24))),
+            None,
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(10u128.pow(24) + STORAGE_FOR_MINT))
+            .build());
+
+        contract.nft_buy("1".to_string());
+        assert!(get_transfers().contains(&(accounts(3), 95 * 10u128.pow(22))));
+    }
+
+    #[test]
+    fn test_reject_creator_application() {
+        let (mut context, mut contract) = setup_contract();
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        contract.submit_creator_application("Band".to_string(), None, None);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        contract.reject_creator_application(accounts(2), Some("Unknown band".to_string()));
+
+        // The applicant gets the storage of the application back
+        assert!(contract.get_creator_application(accounts(2)).is_none());
+        assert!(get_transfers()
+            .iter()
+            .any(|(account_id, amount)| *account_id == accounts(2) && *amount > 0));
+    }
+
+    #[test]
+    #[should_panic(expected = "Marketplace: Admin only")]
+    fn test_reject_creator_application_not_admin() {
+        let (mut context, mut contract) = setup_contract();
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        contract.submit_creator_application("Band".to_string(), None, None);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        contract.reject_creator_application(accounts(2), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Marketplace: Creator is no longer whitelisted")]
+    fn test_buy_removed_creator_series() {
+        let (mut context, mut contract) = setup_contract();
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        let royalty: HashMap<AccountId, u32> = HashMap::new();
+        create_series(
+            &mut contract,
+            &royalty,
+            Some(U128::from(10u128.pow(24))),
+            None,
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        contract.remove_whitelisted_creator(accounts(1));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(10u128.pow(24) + STORAGE_FOR_MINT))
+            .build());
+
+        contract.nft_buy("1".to_string());
+    }
+
+    // Proposes a new owner through the timelock and executes the proposal
+    fn propose_owner(context: &mut VMContextBuilder, contract: &mut Contract) {
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        let operation = contract.propose_owner(accounts(5));
+        assert_eq!(contract.get_proposed_owner(), None);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .block_timestamp(operation.executable_at as u64 * 10u64.pow(9))
+            .attached_deposit(NearToken::from_yoctonear(0))
+            .build());
+
+        contract.execute_timelock_operation(operation.operation_id);
+    }
+
+    #[test]
+    fn test_transfer_ownership() {
+        let (mut context, mut contract) = setup_contract();
+        propose_owner(&mut context, &mut contract);
+        assert_eq!(contract.get_owner(), accounts(0));
+        assert_eq!(contract.get_proposed_owner(), Some(accounts(5)));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(5))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        contract.accept_owner();
+        assert_eq!(contract.get_owner(), accounts(5));
+        assert_eq!(contract.get_proposed_owner(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Marketplace: Proposed owner only")]
+    fn test_accept_cancelled_owner_proposal() {
+        let (mut context, mut contract) = setup_contract();
+        propose_owner(&mut context, &mut contract);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        contract.cancel_owner_proposal();
+
+        testing_env!(context
+            .predecessor_account_id(accounts(5))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        contract.accept_owner();
+    }
+
+    #[test]
+    #[should_panic(expected = "Marketplace: Owner only")]
+    fn test_admin_propose_owner() {
+        let (mut context, mut contract) = setup_contract();
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        let operation = contract.add_admin(accounts(3));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .block_timestamp(operation.executable_at as u64 * 10u64.pow(9))
+            .attached_deposit(NearToken::from_yoctonear(0))
+            .build());
+
+        contract.execute_timelock_operation(operation.operation_id);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        contract.schedule_timelock_operation(TimelockAction::ProposeOwner {
+            new_owner_id: accounts(3),
+        });
+    }
+
+    #[test]
+    fn test_timelock_set_treasury() {
+        let (mut context, mut contract) = setup_contract();
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        let operation = contract.set_treasury(accounts(5));
+        assert_eq!(
+            contract.get_treasury(),
+            HashMap::from([(accounts(4), 10_000)])
+        );
+        assert_eq!(operation.executable_at, crate::timelock::TIMELOCK_DELAY_SEC);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .block_timestamp(operation.executable_at as u64 * 10u64.pow(9))
+            .attached_deposit(NearToken::from_yoctonear(0))
+            .build());
+
+        contract.execute_timelock_operation(operation.operation_id.clone());
+        assert_eq!(
+            contract.get_treasury(),
+            HashMap::from([(accounts(5), 10_000)])
+        );
+        assert!(contract
+            .get_timelock_operation(operation.operation_id)
+            .is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Marketplace: treasury weight is more than 10_000")]
+    fn test_timelock_set_treasury_wrapping_weights() {
+        let (mut context, mut contract) = setup_contract();
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        // Sums to 10_000 once it wraps around u32
+        contract.set_treasury_weights(HashMap::from([
+            (accounts(4), u32::MAX),
+            (accounts(5), 10_001),
+        ]));
+    }
+
+    #[test]
+    #[should_panic(expected = "Marketplace: Timelock operation executable at")]
+    fn test_timelock_execute_too_early() {
+        let (mut context, mut contract) = setup_contract();
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        let operation =
+            contract.schedule_timelock_operation(TimelockAction::SetTransactionFee { fee: 2000 });
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(NearToken::from_yoctonear(0))
+            .build());
+
+        contract.execute_timelock_operation(operation.operation_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Marketplace: Timelock operation not exist")]
+    fn test_timelock_execute_cancelled() {
+        let (mut context, mut contract) = setup_contract();
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        let operation = contract.set_treasury(accounts(5));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        contract.cancel_timelock_operation(operation.operation_id.clone());
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .block_timestamp(operation.executable_at as u64 * 10u64.pow(9))
+            .attached_deposit(NearToken::from_yoctonear(0))
+            .build());
+
+        contract.execute_timelock_operation(operation.operation_id);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Marketplace: transaction fee above 1000 must go through the timelock"
+    )]
+    fn test_set_transaction_fee_above_threshold() {
+        let (mut context, mut contract) = setup_contract();
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        contract.set_transaction_fee(2000, None);
+    }
+
+    #[test]
+    fn test_timelock_upgrade() {
+        let (mut context, mut contract) = setup_contract();
+        let code = b"wasm".to_vec();
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        let operation = contract.schedule_timelock_operation(TimelockAction::UpgradeCode {
+            code_hash: Base64VecU8(env::sha256(&code)),
+        });
+
+        context.context.input = code.clone();
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .block_timestamp(operation.executable_at as u64 * 10u64.pow(9))
+            .attached_deposit(NearToken::from_yoctonear(0))
+            .build());
+
+        contract.execute_timelock_upgrade();
+        let actions: Vec<_> = near_sdk::test_utils::get_created_receipts()
+            .into_iter()
+            .flat_map(|receipt| receipt.actions)
+            .collect();
+        assert!(matches!(
+            &actions[..],
+            [
+                near_sdk::mock::MockAction::DeployContract { code: deployed, .. },
+                near_sdk::mock::MockAction::FunctionCallWeight { method_name, .. },
+            ] if *deployed == code && method_name == b"migrate"
+        ));
+    }
+
+    #[test]
+    fn test_migrate_first_release_state() {
+        use crate::migration::{ContractV1, TokenSeriesV1, TransactionFeeV1};
+
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+
+        let mut old = ContractV1 {
+            tokens: NonFungibleToken::new(
+                StorageKey::NonFungibleToken,
+                accounts(0),
+                Some(StorageKey::TokenMetadata),
+                Some(StorageKey::Enumeration),
+                Some(StorageKey::Approval),
+            ),
+            metadata: LazyOption::new(StorageKey::Metadata, None),
+            token_series_by_id: UnorderedMap::new(StorageKey::TokenSeriesById),
+            treasury_id: accounts(4),
+            transaction_fee: TransactionFeeV1 {
+                next_fee: None,
+                start_time: None,
+                current_fee: 500,
+            },
+            market_data_transaction_fee: MarketDataTransactionFee {
+                transaction_fee: UnorderedMap::new(StorageKey::MarketDataTransactionFee),
+            },
+            whitelisted_creators: UnorderedSet::new(StorageKey::WhitelistedCreators),
+            royalty_paid_on_buy: true,
+        };
+        old.whitelisted_creators.insert(&accounts(1));
+        old.token_series_by_id.insert(
+            &"1".to_string(),
+            &TokenSeriesV1 {
+                metadata: TokenMetadata {
+                    title: Some("title".to_string()),
+                    description: None,
+                    media: Some("media".to_string()),
+                    media_hash: None,
+                    copies: None,
+                    issued_at: None,
+                    expires_at: None,
+                    starts_at: None,
+                    updated_at: None,
+                    extra: None,
+                    reference: Some("reference".to_string()),
+                    reference_hash: None,
+                },
+                creator_id: accounts(1),
+                tokens: UnorderedSet::new(StorageKey::TokensBySeriesInner {
+                    token_series: "1".to_string(),
+                }),
+                price: Some(10u128.pow(24)),
+                is_mintable: true,
+                royalty: HashMap::from([(accounts(1), 1000)]),
+                start_time: None,
+                end_time: None,
+            },
+        );
+        env::state_write(&old);
+
+        let mut contract = Contract::migrate();
+        assert_eq!(
+            contract.get_treasury(),
+            HashMap::from([(accounts(4), 10_000)])
+        );
+
+        let token_series = contract.nft_get_series_single("1".to_string());
+        assert_eq!(token_series.creator_id, accounts(1));
+        assert!(token_series.royalty_paid_on_buy);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(10u128.pow(24) + STORAGE_FOR_MINT))
+            .build());
+
+        let token = contract.nft_buy("1".to_string());
+        assert_eq!(token.token_id, "1:1");
+        assert!(get_transfers().contains(&(accounts(4), 5 * 10u128.pow(22))));
+    }
+
+    fn setup_primary_split_series(context: &mut VMContextBuilder, contract: &mut Contract) {
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        let mut royalty: HashMap<AccountId, u32> = HashMap::new();
+        royalty.insert(accounts(2), 1000);
+        create_series(contract, &royalty, Some(U128::from(10u128.pow(24))), None);
+    }
+
+    #[test]
+    fn test_buy_with_primary_split() {
+        let (mut context, mut contract) = setup_contract();
+        setup_primary_split_series(&mut context, &mut contract);
+
+        let mut primary_split: HashMap<AccountId, u32> = HashMap::new();
+        primary_split.insert(accounts(3), 3000);
+        primary_split.insert(accounts(5), 2000);
+        contract.nft_set_series_primary_split("1".to_string(), Some(primary_split), None);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(10u128.pow(24) + STORAGE_FOR_MINT))
+            .build());
+
+        contract.nft_buy("1".to_string());
+
+        let transfers = get_transfers();
+        assert!(transfers.contains(&(accounts(3), 285 * 10u128.pow(21))));
+        assert!(transfers.contains(&(accounts(5), 190 * 10u128.pow(21))));
+        assert!(transfers.contains(&(accounts(1), 475 * 10u128.pow(21))));
+        // Royalty holder gets nothing on the primary sale
+        assert!(!transfers.contains(&(accounts(2), 95 * 10u128.pow(21))));
+    }
+
+    #[test]
+    fn test_buy_without_royalty_paid_on_buy() {
+        let (mut context, mut contract) = setup_contract();
+        setup_primary_split_series(&mut context, &mut contract);
+
+        contract.nft_set_series_primary_split("1".to_string(), None, Some(false));
+        assert!(
+            !contract
+                .nft_get_series_single("1".to_string())
+                .royalty_paid_on_buy
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(NearToken::from_yoctonear(10u128.pow(24) + STORAGE_FOR_MINT))
+            .build());
+
+        contract.nft_buy("1".to_string());
+
+        assert!(get_transfers().contains(&(accounts(1), 95 * 10u128.pow(22))));
+    }
+
+    #[test]
+    #[should_panic(expected = "Marketplace: primary split is more than 10_000")]
+    fn test_primary_split_too_large() {
+        let (mut context, mut contract) = setup_contract();
+        setup_primary_split_series(&mut context, &mut contract);
+
+        let mut primary_split: HashMap<AccountId, u32> = HashMap::new();
+        primary_split.insert(accounts(3), 6000);
+        primary_split.insert(accounts(5), 6000);
+        contract.nft_set_series_primary_split("1".to_string(), Some(primary_split), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Marketplace: primary split weight is more than 10_000")]
+    fn test_primary_split_wrapping_weights() {
+        let (mut context, mut contract) = setup_contract();
+        setup_primary_split_series(&mut context, &mut contract);
+
+        // Sums to 1_000 once it wraps around u32
+        let mut primary_split: HashMap<AccountId, u32> = HashMap::new();
+        primary_split.insert(accounts(3), u32::MAX);
+        primary_split.insert(accounts(5), 1_001);
+        contract.nft_set_series_primary_split("1".to_string(), Some(primary_split), None);
+    }
+
+    #[test]
+    fn test_nft_transfer_payout_with_secondary_fee() {
+        let (mut context, mut contract) = setup_contract();
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        contract.set_secondary_fee(250);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        let mut royalty: HashMap<AccountId, u32> = HashMap::new();
+        royalty.insert(accounts(1), 1000);
+        create_series(&mut contract, &royalty, None, None);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_MINT))
+            .build());
+
+        let token = contract.nft_mint("1".to_string(), accounts(2));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        let payout = contract.nft_transfer_payout(
+            accounts(3),
+            token.token_id,
+            Some(0),
+            None,
+            U128::from(10u128.pow(24)),
+            Some(2),
+        );
+
+        assert_eq!(
+            payout.payout[&accounts(1)].0,
+            (1000 * (10u128.pow(24))) / 10_000
+        );
+        assert_eq!(
+            payout.payout[&accounts(2)].0,
+            (8750 * (10u128.pow(24))) / 10_000
+        );
+
+        // The receipt storage comes out of the secondary fee
+        let storage_cost = payout.payout[&env::current_account_id()].0;
+        assert!(storage_cost > 0);
+        assert_eq!(
+            payout.payout[&accounts(4)].0 + storage_cost,
+            (250 * (10u128.pow(24))) / 10_000
+        );
+        assert!(near_sdk::test_utils::get_logs()
+            .iter()
+            .any(|log| log.contains("\"event\":\"nft_resale\"")));
+    }
+
+    #[test]
+    fn test_nft_payout_secondary_fee_capped_by_royalty() {
+        let (mut context, mut contract) = setup_contract();
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        let operation =
+            contract.schedule_timelock_operation(TimelockAction::SetSecondaryFee { fee: 3_000 });
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .block_timestamp(operation.executable_at as u64 * 10u64.pow(9))
+            .attached_deposit(NearToken::from_yoctonear(0))
+            .build());
+
+        contract.execute_timelock_operation(operation.operation_id);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        let mut royalty: HashMap<AccountId, u32> = HashMap::new();
+        royalty.insert(accounts(1), 9000);
+        create_series(&mut contract, &royalty, None, None);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_MINT))
+            .build());
+
+        let token = contract.nft_mint("1".to_string(), accounts(2));
+
+        let payout = contract.nft_payout(token.token_id, U128::from(10u128.pow(24)), Some(2));
+
+        let mut payout_calc: HashMap<AccountId, U128> = HashMap::new();
+        payout_calc.insert(accounts(1), U128::from((9000 * (10u128.pow(24))) / 10_000));
+        payout_calc.insert(accounts(2), U128::from(0));
+        payout_calc.insert(accounts(4), U128::from((1000 * (10u128.pow(24))) / 10_000));
+        assert_eq!(payout.payout, payout_calc);
+    }
+
+    #[test]
+    fn test_buy_with_weighted_treasury() {
+        let (mut context, mut contract) = setup_contract();
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        let operation = contract
+            .set_treasury_weights(HashMap::from([(accounts(4), 7000), (accounts(5), 3000)]));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(operation.executable_at as u64 * 10u64.pow(9))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        contract.execute_timelock_operation(operation.operation_id);
+
+        let royalty: HashMap<AccountId, u32> = HashMap::new();
+        create_series(
+            &mut contract,
+            &royalty,
+            Some(U128::from(10u128.pow(24))),
+            None,
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(10u128.pow(24) + STORAGE_FOR_MINT))
+            .build());
+
+        contract.nft_buy("1".to_string());
+
+        let transfers = get_transfers();
+        assert!(transfers.contains(&(accounts(4), 35 * 10u128.pow(21))));
+        assert!(transfers.contains(&(accounts(5), 15 * 10u128.pow(21))));
+    }
+
+    #[test]
+    #[should_panic(expected = "Marketplace: treasury weights must sum to 10_000")]
+    fn test_set_treasury_weights_invalid_sum() {
+        let (mut context, mut contract) = setup_contract();
+
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        contract.set_treasury_weights(HashMap::from([(accounts(4), 7000), (accounts(5), 2000)]));
+    }
+
+    #[test]
+    fn test_purchase_quote() {
+        let (mut context, mut contract) = setup_contract();
+        setup_primary_split_series(&mut context, &mut contract);
+
+        let quote = contract.nft_get_purchase_quote("1".to_string(), accounts(3), None);
+        assert!(quote.can_purchase);
+        assert_eq!(quote.reason, None);
+        assert_eq!(quote.platform_fee.0, 5 * 10u128.pow(22));
+        assert_eq!(
+            quote.split.get(&accounts(2)).unwrap().0,
+            95 * 10u128.pow(21)
+        );
+        assert_eq!(quote.creator_payout_id, accounts(1));
+        assert_eq!(quote.creator_net.0, 855 * 10u128.pow(21));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(NearToken::from_yoctonear(quote.total_deposit.0))
+            .build());
+
+        let initial_storage_usage = env::storage_usage();
+        contract.nft_buy("1".to_string());
+        let storage_used = (env::storage_usage() - initial_storage_usage) as u128;
+
+        assert!(quote.storage_deposit.0 >= storage_used * env::storage_byte_cost().as_yoctonear());
+        let transfers = get_transfers();
+        assert!(transfers.contains(&(accounts(2), quote.split[&accounts(2)].0)));
+        assert!(transfers.contains(&(accounts(1), quote.creator_net.0)));
+    }
+
+    #[test]
+    fn test_purchase_quote_blocked() {
+        let (mut context, mut contract) = setup_contract();
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        create_series(
+            &mut contract,
+            &HashMap::new(),
+            Some(U128::from(10u128.pow(24))),
+            Some(1),
+        );
+
+        let quote = contract.nft_get_purchase_quote("1".to_string(), accounts(3), Some(2));
+        assert!(!quote.can_purchase);
+        assert_eq!(
+            quote.reason,
+            Some("Marketplace: Only 1 copies left".to_string())
+        );
+        assert_eq!(quote.total_price.0, 2 * 10u128.pow(24));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(NearToken::from_yoctonear(10u128.pow(24) + STORAGE_FOR_MINT))
+            .build());
+
+        contract.nft_buy("1".to_string());
+
+        let quote = contract.nft_get_purchase_quote("1".to_string(), accounts(3), None);
+        assert!(!quote.can_purchase);
+        assert_eq!(quote.reason, Some("Marketplace: not for sale".to_string()));
+    }
+
+    #[test]
+    fn test_purchase_quote_fees_more_than_price() {
+        let (mut context, mut contract) = setup_contract();
+        setup_primary_split_series(&mut context, &mut contract);
+
+        // Fee left over from before fees were capped
+        contract
+            .market_data_transaction_fee
+            .transaction_fee
+            .insert(&"1".to_string(), &20_000);
+
+        let quote = contract.nft_get_purchase_quote("1".to_string(), accounts(3), None);
+        assert!(!quote.can_purchase);
+        assert_eq!(
+            quote.reason,
+            Some("Marketplace: fees are more than price".to_string())
+        );
+        assert_eq!(quote.creator_net.0, 0);
+    }
+
+    #[test]
+    fn test_purchase_quote_covers_long_account_ids() {
+        let (mut context, mut contract) = setup_contract();
+        let long_id = |c: char| -> AccountId { c.to_string().repeat(64).parse().unwrap() };
+
+        contract.whitelisted_creators.insert(&long_id('c'));
+        testing_env!(context
+            .predecessor_account_id(long_id('c'))
+            .attached_deposit(NearToken::from_yoctonear(2 * STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        let primary_split = HashMap::from([(long_id('p'), 1_000), (long_id('q'), 1_000)]);
+        create_series(
+            &mut contract,
+            &HashMap::new(),
+            Some(U128::from(10u128.pow(24))),
+            None,
+        );
+        contract.nft_set_series_primary_split("1".to_string(), Some(primary_split), None);
+
+        let quote = contract.nft_get_purchase_quote("1".to_string(), long_id('b'), None);
+        testing_env!(context
+            .predecessor_account_id(long_id('b'))
+            .attached_deposit(NearToken::from_yoctonear(quote.total_deposit.0))
+            .build());
+
+        let initial_storage_usage = env::storage_usage();
+        contract.nft_buy("1".to_string());
+        let storage_used = (env::storage_usage() - initial_storage_usage) as u128;
+
+        assert!(quote.storage_deposit.0 >= storage_used * env::storage_byte_cost().as_yoctonear());
+    }
+
+    #[test]
+    fn test_sale_receipts() {
+        let (mut context, mut contract) = setup_contract();
+        setup_primary_split_series(&mut context, &mut contract);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(NearToken::from_yoctonear(10u128.pow(24) + STORAGE_FOR_MINT))
+            .build());
+
+        let token = contract.nft_buy("1".to_string());
+
+        let receipts = contract.get_receipts_by_token(token.token_id.clone());
+        assert_eq!(receipts.len(), 1);
+        let receipt = &receipts[0];
+        assert_eq!(receipt.sale_type, SaleType::Primary);
+        assert_eq!(receipt.buyer_id, accounts(3));
+        assert_eq!(receipt.seller_id, accounts(1));
+        assert_eq!(receipt.price.0, 10u128.pow(24));
+        assert_eq!(receipt.currency, "NEAR".to_string());
+        assert_eq!(receipt.platform_fee.0, 5 * 10u128.pow(22));
+        assert_eq!(receipt.royalties[&accounts(2)].0, 95 * 10u128.pow(21));
+        assert_eq!(receipt.seller_net.0, 855 * 10u128.pow(21));
+        assert!(near_sdk::test_utils::get_logs()
+            .iter()
+            .any(|log| log.contains("\"event\":\"nft_sale_receipt\"")));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        contract.nft_transfer_payout(
+            accounts(5),
+            token.token_id.clone(),
+            None,
+            None,
+            U128::from(2 * 10u128.pow(24)),
+            Some(2),
+        );
+
+        let receipts = contract.get_receipts_by_token(token.token_id);
+        assert_eq!(receipts.len(), 2);
+        let receipt = &receipts[1];
+        assert_eq!(receipt.receipt_id, "2".to_string());
+        assert_eq!(receipt.sale_type, SaleType::Secondary);
+        assert_eq!(receipt.seller_id, accounts(3));
+        assert_eq!(receipt.buyer_id, accounts(5));
+        assert_eq!(receipt.platform_fee.0, 0);
+        assert_eq!(receipt.royalties[&accounts(2)].0, 2 * 10u128.pow(23));
+        assert_eq!(receipt.seller_net.0, 18 * 10u128.pow(23));
+        assert!(contract.get_receipt("2".to_string()).is_some());
+
+        assert_eq!(
+            contract
+                .get_receipts_by_creator(accounts(1), None, None)
+                .len(),
+            2
+        );
+        assert_eq!(
+            contract
+                .get_receipts_by_series("1".to_string(), Some(U128(0)), Some(1))
+                .len(),
+            1
+        );
+        assert!(contract
+            .get_receipts_by_series("2".to_string(), None, None)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_sales_stats() {
+        let (mut context, mut contract) = setup_contract();
+        setup_primary_split_series(&mut context, &mut contract);
+
+        for buyer_id in [accounts(3), accounts(5)] {
+            testing_env!(context
+                .predecessor_account_id(buyer_id)
+                .attached_deposit(NearToken::from_yoctonear(10u128.pow(24) + STORAGE_FOR_MINT))
+                .build());
+
+            contract.nft_buy("1".to_string());
+        }
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        contract.nft_transfer_payout(
+            accounts(5),
+            format!("1{}1", TOKEN_DELIMETER),
+            None,
+            None,
+            U128::from(2 * 10u128.pow(24)),
+            Some(2),
+        );
+
+        let series_stats = contract.get_series_sales_stats("1".to_string());
+        assert_eq!(series_stats.units_sold.0, 2);
+        assert_eq!(series_stats.self_reported_resales.0, 1);
+        assert_eq!(series_stats.primary_volume.0, 2 * 10u128.pow(24));
+        // Resales are reported apart from the sales paid through the contract
+        assert_eq!(series_stats.gross_volume.0, 2 * 10u128.pow(24));
+        assert_eq!(series_stats.platform_fees.0, 10u128.pow(23));
+        assert_eq!(series_stats.royalties_paid.0, 19 * 10u128.pow(22));
+
+        let creator_stats = contract.get_creator_sales_stats(accounts(1));
+        assert_eq!(creator_stats.units_sold.0, 2);
+        assert_eq!(creator_stats.gross_volume.0, 2 * 10u128.pow(24));
+
+        let sales_stats = contract.get_sales_stats();
+        assert_eq!(sales_stats.units_sold.0, 2);
+        assert_eq!(
+            sales_stats.self_reported_secondary_volume.0,
+            2 * 10u128.pow(24)
+        );
+
+        assert_eq!(
+            contract.get_creator_sales_stats(accounts(2)).units_sold.0,
+            0
+        );
+    }
+
+    fn create_series_of_kind(contract: &mut Contract, kind: SeriesKind) -> TokenSeriesJson {
+        contract.nft_create_series(
+            None,
+            TokenMetadata {
+                title: Some("title".to_string()),
+                description: None,
+                media: Some("media".to_string()),
+                media_hash: None,
+                copies: None,
+                issued_at: None,
+                expires_at: None,
+                starts_at: None,
+                updated_at: None,
+                extra: None,
+                reference: None,
+                reference_hash: None,
+            },
+            Some(U128::from(10u128.pow(24))),
+            None,
+            None,
+            None,
+            Some(kind),
+        )
+    }
+
+    #[test]
+    fn test_create_venue_pass_series() {
+        let (mut context, mut contract) = setup_contract();
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .block_timestamp(1_000 * 10u64.pow(9))
+            .build());
+
+        let kind = SeriesKind::VenuePass { validity: 3_600 };
+        let series = create_series_of_kind(&mut contract, kind.clone());
+        assert_eq!(series.kind, kind);
+        assert_eq!(contract.nft_get_series_single("1".to_string()).kind, kind);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_MINT))
+            .build());
+
+        let token = contract.nft_mint("1".to_string(), accounts(2));
+        assert_eq!(contract.nft_pass_valid_until(token.token_id), Some(4_600));
+    }
+
+    #[test]
+    #[should_panic(expected = "Marketplace: Ticket event date must be in the future")]
+    fn test_create_ticket_series_event_in_past() {
+        let (mut context, mut contract) = setup_contract();
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .block_timestamp(1_000 * 10u64.pow(9))
+            .build());
+
+        create_series_of_kind(&mut contract, SeriesKind::Ticket { event_date: 500 });
+    }
+
+    fn setup_merch_series(
+        context: &mut VMContextBuilder,
+        contract: &mut Contract,
+        burn_on_redeem: bool,
+    ) -> Token {
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        create_series_of_kind(
+            contract,
+            SeriesKind::Merchandise {
+                requires_shipping: true,
+                redeem_by: None,
+                burn_on_redeem,
+                redeemed_media: Some("redeemed".to_string()),
+            },
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_MINT))
+            .build());
+
+        let token = contract.nft_mint("1".to_string(), accounts(2));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_MINT))
+            .build());
+
+        token
+    }
+
+    #[test]
+    fn test_redeem_merch_as_collectible() {
+        let (mut context, mut contract) = setup_contract();
+        let token = setup_merch_series(&mut context, &mut contract, false);
+
+        let redemption =
+            contract.nft_redeem_merch(token.token_id.clone(), Some(vec![1; 32].into()));
+        assert!(!redemption.burned);
+
+        let redeemed = contract.nft_token(token.token_id.clone()).unwrap();
+        assert_eq!(redeemed.owner_id, accounts(2));
+        assert_eq!(
+            redeemed.metadata.unwrap().media,
+            Some("redeemed".to_string())
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_MINT))
+            .build());
+
+        contract.nft_set_merch_fulfilment(token.token_id.clone(), "TRACK-1".to_string());
+        assert_eq!(
+            contract
+                .nft_get_merch_redemption(token.token_id)
+                .unwrap()
+                .fulfilment_reference,
+            Some("TRACK-1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_redeem_merch_burns_token() {
+        let (mut context, mut contract) = setup_contract();
+        let token = setup_merch_series(&mut context, &mut contract, true);
+
+        contract.nft_redeem_merch(token.token_id.clone(), Some(vec![1; 32].into()));
+
+        assert!(contract.nft_token(token.token_id.clone()).is_none());
+        assert!(contract
+            .nft_tokens_for_owner(accounts(2), None, None)
+            .is_empty());
+        assert!(
+            contract
+                .nft_get_merch_redemption(token.token_id)
+                .unwrap()
+                .burned
+        );
+    }
+
+    #[test]
+    fn test_supply_for_series_after_burn() {
+        let (mut context, mut contract) = setup_contract();
+        let token = setup_merch_series(&mut context, &mut contract, true);
+
+        contract.nft_redeem_merch(token.token_id, Some(vec![1; 32].into()));
+        assert_eq!(contract.nft_supply_for_series("1".to_string()), U64(0));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_MINT))
+            .build());
+
+        let token = contract.nft_mint("1".to_string(), accounts(2));
+        assert_eq!(token.token_id, "1:2".to_string());
+        assert_eq!(contract.nft_supply_for_series("1".to_string()), U64(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Marketplace: Shipping hash is required")]
+    fn test_redeem_merch_without_shipping_hash() {
+        let (mut context, mut contract) = setup_contract();
+        let token = setup_merch_series(&mut context, &mut contract, false);
+
+        contract.nft_redeem_merch(token.token_id, None);
+    }
+
+    #[test]
+    fn test_check_in_multi_use_pass() {
+        let (mut context, mut contract) = setup_contract();
+        let token_id = setup_venue_series_with_uses(
+            &mut context,
+            &mut contract,
+            Some(PassUses {
+                max_uses: 3,
+                cooldown: Some(3_600),
+            }),
+        );
+        assert_eq!(contract.nft_remaining_uses(token_id.clone()), Some(3));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(5))
+            .block_timestamp(10_000 * 10u64.pow(9))
+            .build());
+
+        assert_eq!(contract.nft_check_in(token_id.clone()).uses, 1);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(5))
+            .block_timestamp(20_000 * 10u64.pow(9))
+            .build());
+
+        assert_eq!(contract.nft_check_in(token_id.clone()).uses, 2);
+        assert_eq!(contract.nft_remaining_uses(token_id.clone()), Some(1));
+
+        let extra = contract
+            .nft_token(token_id)
+            .unwrap()
+            .metadata
+            .unwrap()
+            .extra
+            .unwrap();
+        assert_eq!(extra, "{\"remaining_uses\":1}".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Marketplace: Pass can be used again at 13600")]
+    fn test_check_in_multi_use_pass_cooldown() {
+        let (mut context, mut contract) = setup_contract();
+        let token_id = setup_venue_series_with_uses(
+            &mut context,
+            &mut contract,
+            Some(PassUses {
+                max_uses: 3,
+                cooldown: Some(3_600),
+            }),
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(5))
+            .block_timestamp(10_000 * 10u64.pow(9))
+            .build());
+
+        contract.nft_check_in(token_id.clone());
+        contract.nft_check_in(token_id);
+    }
+
+    fn setup_renewable_pass(context: &mut VMContextBuilder, contract: &mut Contract) -> TokenId {
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .block_timestamp(1_000 * 10u64.pow(9))
+            .build());
+
+        create_series_of_kind(contract, SeriesKind::VenuePass { validity: 3_600 });
+        contract.nft_set_series_renewal(
+            "1".to_string(),
+            Some(PassRenewal {
+                price: U128(10u128.pow(23)),
+                period: 3_600,
+                grace_period: Some(600),
+                max_horizon: Some(10_000),
+            }),
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_MINT))
+            .build());
+
+        contract.nft_mint("1".to_string(), accounts(2)).token_id
+    }
+
+    #[test]
+    fn test_renew_pass() {
+        let (mut context, mut contract) = setup_contract();
+        let token_id = setup_renewable_pass(&mut context, &mut contract);
+        assert_eq!(contract.nft_pass_valid_until(token_id.clone()), Some(4_600));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(10u128.pow(23) + STORAGE_FOR_MINT))
+            .block_timestamp(4_000 * 10u64.pow(9))
             .build());
 
-        contract.calculate_current_transaction_fee();
-        assert_eq!(contract.get_transaction_fee().current_fee, next_fee);
-        assert_eq!(contract.get_transaction_fee().next_fee, None);
-        assert_eq!(contract.get_transaction_fee().start_time, None);
+        assert_eq!(contract.nft_renew(token_id.clone()), 8_200);
+        assert_eq!(contract.nft_pass_valid_until(token_id.clone()), Some(8_200));
+        assert!(get_transfers().contains(&(accounts(1), 95 * 10u128.pow(21))));
+
+        let receipts = contract.get_receipts_by_token(token_id);
+        assert_eq!(receipts[0].sale_type, SaleType::Renewal);
+        assert_eq!(
+            contract.get_series_sales_stats("1".to_string()).renewals.0,
+            1
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Marketplace: Pass expired at 4600")]
+    fn test_renew_pass_after_grace_period() {
+        let (mut context, mut contract) = setup_contract();
+        let token_id = setup_renewable_pass(&mut context, &mut contract);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(10u128.pow(23) + STORAGE_FOR_MINT))
+            .block_timestamp(5_300 * 10u64.pow(9))
+            .build());
+
+        contract.nft_renew(token_id);
+    }
+
+    #[test]
+    fn test_auto_renew_pass() {
+        let (mut context, mut contract) = setup_contract();
+        let token_id = setup_renewable_pass(&mut context, &mut contract);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(10u128.pow(24)))
+            .build());
+
+        let deposit = contract.nft_deposit_renewal(token_id.clone()).amount.0;
+        assert_eq!(
+            contract.get_solvency().liabilities.renewal_deposits.0,
+            deposit
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(5))
+            .attached_deposit(NearToken::from_yoctonear(0))
+            .block_timestamp(4_700 * 10u64.pow(9))
+            .build());
+
+        assert_eq!(contract.nft_auto_renew(token_id.clone()), 8_200);
+
+        let remaining = contract
+            .nft_get_renewal_deposit(token_id.clone())
+            .unwrap()
+            .amount
+            .0;
+        assert!(remaining < deposit - 10u128.pow(23));
+        assert_eq!(
+            contract.get_solvency().liabilities.renewal_deposits.0,
+            remaining
+        );
+
+        // Withdrawing also returns the storage of the deposit slot
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        assert_eq!(contract.nft_withdraw_renewal_deposit(token_id).0, remaining);
+        assert!(get_transfers()
+            .iter()
+            .any(|(account_id, amount)| *account_id == accounts(2) && *amount > remaining));
+        assert_eq!(contract.get_solvency().liabilities.renewal_deposits.0, 0);
+    }
+
+    #[test]
+    fn test_auto_renew_pass_without_grace_period() {
+        let (mut context, mut contract) = setup_contract();
+        let token_id = setup_renewable_pass(&mut context, &mut contract);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_MINT))
+            .build());
+
+        contract.nft_set_series_renewal(
+            "1".to_string(),
+            Some(PassRenewal {
+                price: U128(10u128.pow(23)),
+                period: 3_600,
+                grace_period: None,
+                max_horizon: None,
+            }),
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(10u128.pow(24)))
+            .build());
+
+        contract.nft_deposit_renewal(token_id.clone());
+
+        // Opens a tenth of the period before the pass expires at 4600
+        testing_env!(context
+            .predecessor_account_id(accounts(5))
+            .attached_deposit(NearToken::from_yoctonear(0))
+            .block_timestamp(4_300 * 10u64.pow(9))
+            .build());
+
+        assert_eq!(contract.nft_auto_renew(token_id.clone()), 8_200);
+
+        // A keeper missing the expiry still renews, counting from then
+        testing_env!(context
+            .predecessor_account_id(accounts(5))
+            .attached_deposit(NearToken::from_yoctonear(0))
+            .block_timestamp(9_000 * 10u64.pow(9))
+            .build());
+
+        assert_eq!(contract.nft_auto_renew(token_id), 12_600);
+    }
+
+    #[test]
+    #[should_panic(expected = "Marketplace: Pass is not due for renewal until 4240")]
+    fn test_auto_renew_pass_too_early() {
+        let (mut context, mut contract) = setup_contract();
+        let token_id = setup_renewable_pass(&mut context, &mut contract);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(10u128.pow(24)))
+            .build());
+
+        contract.nft_deposit_renewal(token_id.clone());
+
+        testing_env!(context
+            .predecessor_account_id(accounts(5))
+            .attached_deposit(NearToken::from_yoctonear(0))
+            .block_timestamp(4_000 * 10u64.pow(9))
+            .build());
+
+        contract.nft_auto_renew(token_id);
+    }
+
+    #[test]
+    fn test_renewal_deposit_of_previous_owner_refunded() {
+        let (mut context, mut contract) = setup_contract();
+        let token_id = setup_renewable_pass(&mut context, &mut contract);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(10u128.pow(24)))
+            .build());
+
+        let deposit = contract.nft_deposit_renewal(token_id.clone()).amount.0;
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        contract.nft_transfer(accounts(3), token_id.clone(), None, None);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(NearToken::from_yoctonear(10u128.pow(24)))
+            .build());
+
+        let renewal_deposit = contract.nft_deposit_renewal(token_id);
+        assert_eq!(renewal_deposit.account_id, accounts(3));
+        assert!(get_transfers()
+            .iter()
+            .any(|(account_id, amount)| *account_id == accounts(2) && *amount > deposit));
+        assert_eq!(
+            contract.get_solvency().liabilities.renewal_deposits,
+            renewal_deposit.amount
+        );
+    }
+
+    fn setup_upgrade_series(context: &mut VMContextBuilder, contract: &mut Contract) -> TokenId {
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        let royalty: HashMap<AccountId, u32> = HashMap::new();
+        create_series(
+            contract,
+            &royalty,
+            Some(U128::from(10u128.pow(24))),
+            Some(1),
+        );
+        create_series(
+            contract,
+            &royalty,
+            Some(U128::from(3 * 10u128.pow(24))),
+            Some(10),
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(10u128.pow(24) + STORAGE_FOR_MINT))
+            .build());
+
+        contract.nft_buy("1".to_string()).token_id
+    }
+
+    #[test]
+    fn test_upgrade_price_of_minted_token() {
+        let (mut context, mut contract) = setup_contract();
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        let royalty: HashMap<AccountId, u32> = HashMap::new();
+        create_series(
+            &mut contract,
+            &royalty,
+            Some(U128::from(10u128.pow(24))),
+            Some(10),
+        );
+        create_series(
+            &mut contract,
+            &royalty,
+            Some(U128::from(3 * 10u128.pow(24))),
+            Some(10),
+        );
+        contract.nft_set_series_upgrade_path(
+            "1".to_string(),
+            "2".to_string(),
+            Some(UpgradePath {
+                price: None,
+                return_to_inventory: false,
+            }),
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_MINT))
+            .build());
+
+        // Nothing was paid for the token, so the series price gives no credit
+        let token_id = contract.nft_mint("1".to_string(), accounts(2)).token_id;
+        assert_eq!(
+            contract.nft_get_upgrade_price(token_id, "2".to_string()),
+            U128(3 * 10u128.pow(24))
+        );
+    }
+
+    #[test]
+    fn test_upgrade_token() {
+        let (mut context, mut contract) = setup_contract();
+        let token_id = setup_upgrade_series(&mut context, &mut contract);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        contract.nft_set_series_upgrade_path(
+            "1".to_string(),
+            "2".to_string(),
+            Some(UpgradePath {
+                price: None,
+                return_to_inventory: true,
+            }),
+        );
+        assert_eq!(
+            contract.nft_get_upgrade_price(token_id.clone(), "2".to_string()),
+            U128(2 * 10u128.pow(24))
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(
+                2 * 10u128.pow(24) + STORAGE_FOR_MINT
+            ))
+            .build());
 
+        let token = contract.nft_upgrade(token_id.clone(), "2".to_string());
+        assert_eq!(token.token_id, format!("2{}1", TOKEN_DELIMETER));
+        assert_eq!(token.owner_id, accounts(2));
+        assert!(contract.nft_token(token_id).is_none());
+        assert!(get_transfers().contains(&(accounts(1), 19 * 10u128.pow(23))));
+
+        // The sold out series has its copy back in inventory but stays closed
         let series = contract.nft_get_series_single("1".to_string());
-        let series_transaction_fee: u128 = series.transaction_fee.unwrap().into();
-        assert_eq!(series_transaction_fee, 500);
+        assert_eq!(series.metadata.copies, Some(2));
+        assert!(
+            !contract
+                .token_series_by_id
+                .get(&"1".to_string())
+                .unwrap()
+                .is_mintable
+        );
+        assert_eq!(
+            contract.get_series_sales_stats("2".to_string()).upgrades.0,
+            1
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Marketplace: Upgrade path not allowed")]
+    fn test_upgrade_token_without_path() {
+        let (mut context, mut contract) = setup_contract();
+        let token_id = setup_upgrade_series(&mut context, &mut contract);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(
+                2 * 10u128.pow(24) + STORAGE_FOR_MINT
+            ))
+            .build());
+
+        contract.nft_upgrade(token_id, "2".to_string());
+    }
+
+    #[test]
+    fn test_chained_upgrade_credits_whole_lineage() {
+        let (mut context, mut contract) = setup_contract();
+        let token_id = setup_upgrade_series(&mut context, &mut contract);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        let royalty: HashMap<AccountId, u32> = HashMap::new();
+        create_series(
+            &mut contract,
+            &royalty,
+            Some(U128::from(5 * 10u128.pow(24))),
+            Some(10),
+        );
+        for (from, to) in [("1", "2"), ("2", "3")] {
+            contract.nft_set_series_upgrade_path(
+                from.to_string(),
+                to.to_string(),
+                Some(UpgradePath {
+                    price: None,
+                    return_to_inventory: false,
+                }),
+            );
+        }
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(
+                2 * 10u128.pow(24) + STORAGE_FOR_MINT
+            ))
+            .build());
+
+        let vip = contract.nft_upgrade(token_id, "2".to_string());
+
+        // 1 NEAR for the ticket and 2 NEAR for the first upgrade count towards backstage
+        assert_eq!(
+            contract.nft_get_upgrade_price(vip.token_id.clone(), "3".to_string()),
+            U128(2 * 10u128.pow(24))
+        );
+
+        let backstage = contract.nft_upgrade(vip.token_id, "3".to_string());
+        assert_eq!(backstage.token_id, format!("3{}1", TOKEN_DELIMETER));
+        assert_eq!(
+            contract.get_series_sales_stats("3".to_string()).upgrades.0,
+            1
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Marketplace: Must hold 2 tokens of series 1")]
+    fn test_upgrade_token_to_gated_series() {
+        let (mut context, mut contract) = setup_contract();
+        let token_id = setup_upgrade_series(&mut context, &mut contract);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        contract.nft_set_series_upgrade_path(
+            "1".to_string(),
+            "2".to_string(),
+            Some(UpgradePath {
+                price: None,
+                return_to_inventory: false,
+            }),
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+
+        contract.set_nft_series_gate(
+            "2".to_string(),
+            Some(SeriesGate {
+                token_series_id: "1".to_string(),
+                count: 2,
+                one_per_token: false,
+            }),
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(
+                2 * 10u128.pow(24) + STORAGE_FOR_MINT
+            ))
+            .build());
+
+        contract.nft_upgrade(token_id, "2".to_string());
+    }
+
+    #[test]
+    fn test_upgrade_token_frees_capacity() {
+        let (mut context, mut contract) = setup_contract();
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(STORAGE_FOR_CREATE_SERIES))
+            .build());
+
+        let royalty: HashMap<AccountId, u32> = HashMap::new();
+        create_series(
+            &mut contract,
+            &royalty,
+            Some(U128::from(10u128.pow(24))),
+            None,
+        );
+        create_series(
+            &mut contract,
+            &royalty,
+            Some(U128::from(3 * 10u128.pow(24))),
+            None,
+        );
+
+        let capacity_pool = contract.create_capacity_pool(U64(1));
+        for token_series_id in ["1", "2"] {
+            contract.nft_set_series_capacity_pool(
+                token_series_id.to_string(),
+                Some(capacity_pool.capacity_pool_id.clone()),
+            );
+        }
+        contract.nft_set_series_upgrade_path(
+            "1".to_string(),
+            "2".to_string(),
+            Some(UpgradePath {
+                price: None,
+                return_to_inventory: false,
+            }),
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(10u128.pow(24) + STORAGE_FOR_MINT))
+            .build());
+
+        let token_id = contract.nft_buy("1".to_string()).token_id;
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(NearToken::from_yoctonear(
+                2 * 10u128.pow(24) + STORAGE_FOR_MINT
+            ))
+            .build());
+
+        // The pool is full, only the burned ticket's place lets the upgrade through
+        contract.nft_upgrade(token_id, "2".to_string());
+        assert_eq!(
+            contract
+                .get_capacity_pool(capacity_pool.capacity_pool_id)
+                .unwrap()
+                .minted,
+            U64(1)
+        );
     }
 }
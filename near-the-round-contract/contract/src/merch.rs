@@ -0,0 +1,215 @@
+use crate::*;
+use near_contract_standards::non_fungible_token::events::NftBurn;
+use near_sdk::{
+    borsh::{BorshDeserialize, BorshSerialize},
+    json_types::Base64VecU8,
+    near_bindgen,
+    serde::{Deserialize, Serialize},
+    AccountId,
+};
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub struct MerchRedemption {
+    pub token_id: TokenId,
+    pub owner_id: AccountId,
+    // sha256 of the shipping details, encrypted off-chain for the creator
+    pub shipping_hash: Option<Base64VecU8>,
+    pub redeemed_at: TimestampSec,
+    pub burned: bool,
+    // Carrier tracking number or order id recorded by the creator
+    pub fulfilment_reference: Option<String>,
+    pub fulfilled_at: Option<TimestampSec>,
+}
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn nft_redeem_merch(
+        &mut self,
+        token_id: TokenId,
+        shipping_hash: Option<Base64VecU8>,
+    ) -> MerchRedemption {
+        let initial_storage_usage = env::storage_usage();
+        let owner_id = env::predecessor_account_id();
+
+        assert_eq!(
+            self.tokens.owner_by_id.get(&token_id),
+            Some(owner_id.clone()),
+            "Marketplace: Token owner only"
+        );
+        assert!(
+            self.merch_redemptions.get(&token_id).is_none(),
+            "Marketplace: Token already redeemed"
+        );
+
+        let token_series_id = token_id.split(TOKEN_DELIMETER).next().unwrap().to_string();
+        let token_series = self.token_series_by_id.get(&token_series_id).unwrap();
+        let (requires_shipping, redeem_by, burn_on_redeem, redeemed_media) = match token_series.kind
+        {
+            SeriesKind::Merchandise {
+                requires_shipping,
+                redeem_by,
+                burn_on_redeem,
+                redeemed_media,
+            } => (requires_shipping, redeem_by, burn_on_redeem, redeemed_media),
+            _ => env::panic_str("Marketplace: Token is not merchandise"),
+        };
+
+        if let Some(redeem_by) = redeem_by {
+            assert!(
+                to_sec(env::block_timestamp()) <= redeem_by,
+                "Marketplace: Redemption period ended"
+            );
+        }
+        match &shipping_hash {
+            Some(shipping_hash) => assert_eq!(
+                shipping_hash.0.len(),
+                32,
+                "Marketplace: Shipping hash must be 32 bytes"
+            ),
+            None => assert!(!requires_shipping, "Marketplace: Shipping hash is required"),
+        }
+
+        let redemption = MerchRedemption {
+            token_id: token_id.clone(),
+            owner_id: owner_id.clone(),
+            shipping_hash,
+            redeemed_at: to_sec(env::block_timestamp()),
+            burned: burn_on_redeem,
+            fulfilment_reference: None,
+            fulfilled_at: None,
+        };
+        self.merch_redemptions.insert(&token_id, &redemption);
+
+        if burn_on_redeem {
+            self.internal_burn(&token_id, &owner_id);
+        } else if let Some(redeemed_media) = redeemed_media {
+            // Token stays as a redeemed collectible
+            let token_metadata_by_id = self.tokens.token_metadata_by_id.as_mut().unwrap();
+            let mut token_metadata = token_metadata_by_id.get(&token_id).unwrap();
+            token_metadata.media = Some(redeemed_media);
+            token_metadata.updated_at = Some(env::block_timestamp().to_string());
+            token_metadata_by_id.insert(&token_id, &token_metadata);
+        }
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"nft_redeem_merch",
+            "data": &redemption
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+
+        refund_deposit(
+            env::storage_usage().saturating_sub(initial_storage_usage),
+            0,
+        );
+
+        redemption
+    }
+
+    #[payable]
+    pub fn nft_set_merch_fulfilment(&mut self, token_id: TokenId, fulfilment_reference: String) {
+        let initial_storage_usage = env::storage_usage();
+
+        let token_series_id = token_id.split(TOKEN_DELIMETER).next().unwrap().to_string();
+        let token_series = self
+            .token_series_by_id
+            .get(&token_series_id)
+            .expect("Marketplace: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "Marketplace: Creator only"
+        );
+
+        let mut redemption = self
+            .merch_redemptions
+            .get(&token_id)
+            .expect("Marketplace: Token not redeemed");
+        redemption.fulfilment_reference = Some(fulfilment_reference);
+        redemption.fulfilled_at = Some(to_sec(env::block_timestamp()));
+        self.merch_redemptions.insert(&token_id, &redemption);
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"nft_merch_fulfilment",
+            "data": {
+                    "token_id": token_id,
+                    "fulfilment_reference": redemption.fulfilment_reference,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+
+        refund_deposit(
+            env::storage_usage().saturating_sub(initial_storage_usage),
+            0,
+        );
+    }
+
+    pub fn nft_get_merch_redemption(&self, token_id: TokenId) -> Option<MerchRedemption> {
+        self.merch_redemptions.get(&token_id)
+    }
+
+    // Removes the token from its owner, the id stays in the series so it is never reused
+    pub(crate) fn internal_burn(&mut self, token_id: &TokenId, owner_id: &AccountId) {
+        self.tokens.owner_by_id.remove(token_id);
+
+        let token_series_id: TokenSeriesId =
+            token_id.split(TOKEN_DELIMETER).next().unwrap().to_string();
+        let burned = self
+            .burned_tokens_by_series
+            .get(&token_series_id)
+            .unwrap_or(0);
+        self.burned_tokens_by_series
+            .insert(&token_series_id, &(burned + 1));
+
+        // The burned token no longer takes up a place in its capacity pool
+        let token_series = self.token_series_by_id.get(&token_series_id).unwrap();
+        if let Some(capacity_pool_id) = &token_series.capacity_pool_id {
+            let mut capacity_pool = self.capacity_pools.get(capacity_pool_id).unwrap();
+            capacity_pool.minted -= 1;
+            self.capacity_pools.insert(capacity_pool_id, &capacity_pool);
+        }
+
+        if let Some(token_metadata_by_id) = &mut self.tokens.token_metadata_by_id {
+            token_metadata_by_id.remove(token_id);
+        }
+
+        if let Some(tokens_per_owner) = &mut self.tokens.tokens_per_owner {
+            let mut owner_tokens = tokens_per_owner.get(owner_id).unwrap();
+            owner_tokens.remove(token_id);
+            if owner_tokens.is_empty() {
+                tokens_per_owner.remove(owner_id);
+            } else {
+                tokens_per_owner.insert(owner_id, &owner_tokens);
+            }
+        }
+
+        if let Some(approvals_by_id) = &mut self.tokens.approvals_by_id {
+            approvals_by_id.remove(token_id);
+        }
+        if let Some(next_approval_id_by_id) = &mut self.tokens.next_approval_id_by_id {
+            next_approval_id_by_id.remove(token_id);
+        }
+
+        NftBurn {
+            owner_id,
+            token_ids: &[token_id],
+            authorized_id: None,
+            memo: None,
+        }
+        .emit();
+    }
+}
@@ -0,0 +1,103 @@
+use crate::*;
+use near_sdk::{
+    borsh::{BorshDeserialize, BorshSerialize},
+    near_bindgen,
+};
+
+// State layout of the first release, before gating, events, venues, receipts
+// and the rest were added
+#[derive(BorshDeserialize, BorshSerialize)]
+#[borsh(crate = "near_sdk::borsh")]
+pub(crate) struct ContractV1 {
+    pub tokens: NonFungibleToken,
+    pub metadata: LazyOption<NFTContractMetadata>,
+    pub token_series_by_id: UnorderedMap<TokenSeriesId, TokenSeriesV1>,
+    pub treasury_id: AccountId,
+    pub transaction_fee: TransactionFeeV1,
+    pub market_data_transaction_fee: MarketDataTransactionFee,
+    pub whitelisted_creators: UnorderedSet<AccountId>,
+    pub royalty_paid_on_buy: bool,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+#[borsh(crate = "near_sdk::borsh")]
+pub(crate) struct TokenSeriesV1 {
+    pub metadata: TokenMetadata,
+    pub creator_id: AccountId,
+    pub tokens: UnorderedSet<TokenId>,
+    pub price: Option<Balance>,
+    pub is_mintable: bool,
+    pub royalty: HashMap<AccountId, u32>,
+    pub start_time: Option<TimestampSec>,
+    pub end_time: Option<TimestampSec>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+#[borsh(crate = "near_sdk::borsh")]
+pub(crate) struct TransactionFeeV1 {
+    pub next_fee: Option<u16>,
+    pub start_time: Option<TimestampSec>,
+    pub current_fee: u16,
+}
+
+#[near_bindgen]
+impl Contract {
+    // Called once after deploying over first release state. Every series is
+    // rewritten in the new layout, the new collections start empty
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let mut old: ContractV1 = env::state_read().expect("Marketplace: No state to migrate");
+
+        // Inserting over an old value would read it back in the new layout, so the
+        // map is emptied first and refilled under the same prefix
+        let old_token_series = old.token_series_by_id.to_vec();
+        old.token_series_by_id.clear();
+        let mut token_series_by_id: UnorderedMap<TokenSeriesId, TokenSeries> =
+            UnorderedMap::try_from_slice(
+                &near_sdk::borsh::to_vec(&old.token_series_by_id).unwrap(),
+            )
+            .unwrap();
+        for (token_series_id, token_series) in old_token_series {
+            token_series_by_id.insert(
+                &token_series_id,
+                &TokenSeries {
+                    metadata: token_series.metadata,
+                    creator_id: token_series.creator_id,
+                    tokens: token_series.tokens,
+                    price: token_series.price,
+                    is_mintable: token_series.is_mintable,
+                    royalty: token_series.royalty,
+                    start_time: token_series.start_time,
+                    end_time: token_series.end_time,
+                    gate: None,
+                    event_id: None,
+                    venue_id: None,
+                    capacity_pool_id: None,
+                    primary_split: HashMap::new(),
+                    royalty_paid_on_buy: old.royalty_paid_on_buy,
+                    kind: SeriesKind::default(),
+                    pass_uses: None,
+                    renewal: None,
+                    upgrade_paths: HashMap::new(),
+                },
+            );
+        }
+
+        Self::internal_new(
+            old.tokens,
+            old.metadata,
+            token_series_by_id,
+            HashMap::from([(old.treasury_id, 10_000)]),
+            TransactionFee {
+                next_fee: old.transaction_fee.next_fee,
+                start_time: old.transaction_fee.start_time,
+                current_fee: old.transaction_fee.current_fee,
+                queued_fees: vec![],
+            },
+            old.market_data_transaction_fee,
+            old.whitelisted_creators,
+            old.royalty_paid_on_buy,
+        )
+    }
+}
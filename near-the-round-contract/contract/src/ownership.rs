@@ -0,0 +1,103 @@
+use crate::*;
+use near_sdk::{near_bindgen, AccountId};
+
+#[near_bindgen]
+impl Contract {
+    // The proposal only takes effect once the timelock operation is executed
+    #[payable]
+    pub fn propose_owner(&mut self, new_owner_id: AccountId) -> TimelockOperation {
+        self.schedule_timelock_operation(TimelockAction::ProposeOwner { new_owner_id })
+    }
+
+    #[payable]
+    pub fn cancel_owner_proposal(&mut self) {
+        assert_one_yocto();
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.tokens.owner_id,
+            "Marketplace: Owner only"
+        );
+
+        let proposed_owner_id = self
+            .proposed_owner_id
+            .take()
+            .expect("Marketplace: No owner proposed");
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"owner_proposal_cancel",
+            "data": {
+                    "owner_id": self.tokens.owner_id,
+                    "proposed_owner_id": proposed_owner_id,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+    }
+
+    #[payable]
+    pub fn accept_owner(&mut self) {
+        assert_one_yocto();
+        assert_eq!(
+            Some(env::predecessor_account_id()),
+            self.proposed_owner_id,
+            "Marketplace: Proposed owner only"
+        );
+
+        let previous_owner_id = std::mem::replace(
+            &mut self.tokens.owner_id,
+            self.proposed_owner_id.take().unwrap(),
+        );
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"owner_accept",
+            "data": {
+                    "previous_owner_id": previous_owner_id,
+                    "owner_id": self.tokens.owner_id,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+    }
+
+    pub fn get_owner(&self) -> AccountId {
+        self.tokens.owner_id.clone()
+    }
+
+    pub fn get_proposed_owner(&self) -> Option<AccountId> {
+        self.proposed_owner_id.clone()
+    }
+
+    pub(crate) fn internal_propose_owner(&mut self, new_owner_id: AccountId) {
+        assert_ne!(
+            new_owner_id, self.tokens.owner_id,
+            "Marketplace: Already the owner"
+        );
+
+        self.proposed_owner_id = Some(new_owner_id.clone());
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"owner_propose",
+            "data": {
+                    "owner_id": self.tokens.owner_id,
+                    "proposed_owner_id": new_owner_id,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+    }
+}
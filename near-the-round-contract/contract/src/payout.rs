@@ -1,7 +1,7 @@
 use crate::*;
 use near_sdk::{
     assert_one_yocto,
-    borsh::{self, BorshDeserialize, BorshSerialize},
+    borsh::{BorshDeserialize, BorshSerialize},
     json_types::U128,
     near_bindgen,
     serde::{Deserialize, Serialize},
@@ -11,6 +11,7 @@ use near_sdk::{
 use std::collections::HashMap;
 
 #[derive(Default, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[borsh(crate = "near_sdk::borsh")]
 pub struct Payout {
     pub payout: HashMap<AccountId, U128>,
 }
@@ -36,39 +37,42 @@ impl Payouts for Contract {
         let owner_id = self.tokens.owner_by_id.get(&token_id).expect("No token id");
         let mut token_id_iter = token_id.split(TOKEN_DELIMETER);
         let token_series_id = token_id_iter.next().unwrap().parse().unwrap();
-        let royalty = self
+        let token_series = self
             .token_series_by_id
             .get(&token_series_id)
-            .expect("no type")
-            .royalty;
+            .expect("no type");
 
         let max_length_of_payouts = max_len_payout.unwrap_or_default();
 
+        // Treasury recipients take extra entries when a secondary fee is set
+        let treasury_len = if self.secondary_fee > 0 {
+            self.treasury.len() as u32
+        } else {
+            0
+        };
         assert!(
-            royalty.len() as u32 <= max_length_of_payouts,
+            token_series.royalty.len() as u32 + treasury_len <= max_length_of_payouts,
             "Market cannot payout to that many receivers"
         );
 
-        let balance_u128: u128 = balance.into();
+        let resale_split = self.internal_resale_split(&token_series, &owner_id, balance.into());
 
         let mut payout: Payout = Payout {
-            payout: HashMap::new(),
+            payout: resale_split
+                .split
+                .into_iter()
+                .map(|(account_id, amount)| (account_id, U128(amount)))
+                .collect(),
         };
-        let mut total_perpetual = 0;
+        payout
+            .payout
+            .insert(resale_split.seller_id, U128(resale_split.seller_net));
 
-        for (k, v) in royalty.iter() {
-            if *k != owner_id {
-                let key = k.clone();
-                payout
-                    .payout
-                    .insert(key, royalty_to_payout(*v, balance_u128));
-                total_perpetual += *v;
-            }
+        // Platform fee on secondary sales
+        for (treasury_id, share) in resale_split.treasury {
+            payout.payout.entry(treasury_id).or_insert(U128(0)).0 += share;
         }
-        payout.payout.insert(
-            owner_id,
-            royalty_to_payout(10000 - total_perpetual, balance_u128),
-        );
+
         payout
     }
 
@@ -83,13 +87,104 @@ impl Payouts for Contract {
         max_len_payout: Option<u32>,
     ) -> Payout {
         assert_one_yocto();
-        let payout = self.nft_payout(token_id.clone(), balance, max_len_payout);
-        self.nft_transfer(
+        let owner_id = self.tokens.owner_by_id.get(&token_id).expect("No token id");
+        let mut payout = self.nft_payout(token_id.clone(), balance, max_len_payout);
+        self.nft_transfer(receiver_id.clone(), token_id.clone(), approval_id, memo);
+
+        let token_series_id = token_id.split(TOKEN_DELIMETER).next().unwrap().to_string();
+        let token_series = self.token_series_by_id.get(&token_series_id).unwrap();
+        let resale_split = self.internal_resale_split(&token_series, &owner_id, balance.0);
+        let treasury = resale_split.treasury.clone();
+
+        let initial_storage_usage = env::storage_usage();
+        self.internal_add_receipt(
+            &token_id,
+            &token_series,
             receiver_id.clone(),
-            token_id.clone(),
-            approval_id.clone(),
-            memo.clone(),
+            balance.0,
+            resale_split,
+            SaleType::Secondary,
+        );
+
+        // The secondary fee pays for the receipt storage, the contract covers what it can't
+        let platform_fee: Balance = treasury.iter().map(|(_, share)| share).sum();
+        let storage_cost = platform_fee.min(
+            Balance::from(env::storage_usage().saturating_sub(initial_storage_usage))
+                * env::storage_byte_cost().as_yoctonear(),
         );
+        if storage_cost > 0 {
+            for (treasury_id, share) in treasury {
+                payout.payout.get_mut(&treasury_id).unwrap().0 -= share;
+            }
+            for (treasury_id, share) in self.internal_treasury_split(platform_fee - storage_cost) {
+                payout.payout.get_mut(&treasury_id).unwrap().0 += share;
+            }
+            payout
+                .payout
+                .entry(env::current_account_id())
+                .or_insert(U128(0))
+                .0 += storage_cost;
+        }
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"nft_resale",
+            "data": {
+                    "token_id": token_id,
+                    "owner_id": owner_id,
+                    "receiver_id": receiver_id,
+                    "balance": balance,
+                    "secondary_fee": self.secondary_fee,
+                    "payout": payout.payout,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+
         payout
     }
 }
+
+impl Contract {
+    // Splits a resale balance between royalty accounts, treasury and the seller
+    pub(crate) fn internal_resale_split(
+        &self,
+        token_series: &TokenSeries,
+        owner_id: &AccountId,
+        balance: Balance,
+    ) -> SaleSplit {
+        let mut split = HashMap::new();
+        let mut total_perpetual = 0;
+
+        for (k, v) in token_series.royalty.iter() {
+            if k != owner_id {
+                split.insert(k.clone(), royalty_to_payout(*v, balance).0);
+                total_perpetual += *v;
+            }
+        }
+
+        // The secondary fee only takes what royalties leave, the seller may end up with nothing
+        let secondary_fee =
+            (self.secondary_fee as u32).min(10000u32.saturating_sub(total_perpetual));
+        total_perpetual += secondary_fee;
+
+        // Treasury only takes entries when a secondary fee is set
+        let treasury = if secondary_fee > 0 {
+            self.internal_treasury_split(royalty_to_payout(secondary_fee, balance).0)
+        } else {
+            vec![]
+        };
+
+        SaleSplit {
+            treasury,
+            venue: None,
+            split,
+            seller_id: owner_id.clone(),
+            seller_net: royalty_to_payout(10000 - total_perpetual, balance).0,
+        }
+    }
+}
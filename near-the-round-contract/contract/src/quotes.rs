@@ -0,0 +1,235 @@
+use crate::*;
+use near_sdk::{
+    near_bindgen,
+    serde::{Deserialize, Serialize},
+    AccountId, StorageUsage,
+};
+
+// Storage of a minted token besides its metadata: owner, token id and per owner /
+// per series collection entries. Measured at 756 bytes for a new owner with a 64
+// character account id, rounded up for longer token ids
+const MINT_STORAGE_OVERHEAD: StorageUsage = 800;
+// Storage of a sale receipt, its indexes and the sales counters, plus each split
+// account on it. Measured at 1_021 and 84 bytes on the first sale of a series with
+// 64 character account ids, rounded up for longer receipt ids
+const RECEIPT_STORAGE_OVERHEAD: StorageUsage = 1_100;
+const RECEIPT_STORAGE_PER_SPLIT: StorageUsage = 84;
+
+pub(crate) struct SaleSplit {
+    pub treasury: Vec<(AccountId, Balance)>,
+    pub venue: Option<(AccountId, Balance)>,
+    // Primary split or royalty accounts, without the seller
+    pub split: HashMap<AccountId, Balance>,
+    // Creator payout account on primary sales, previous owner on secondary sales
+    pub seller_id: AccountId,
+    pub seller_net: Balance,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PurchaseQuote {
+    pub token_series_id: TokenSeriesId,
+    pub account_id: AccountId,
+    pub quantity: u64,
+    // Breakdown of a single purchase
+    pub unit_price: U128,
+    pub transaction_fee: u16,
+    pub platform_fee: U128,
+    pub venue_fee: U128,
+    pub split: HashMap<AccountId, U128>,
+    pub creator_payout_id: AccountId,
+    pub creator_net: U128,
+    // Totals for the whole quantity
+    pub total_price: U128,
+    pub storage_deposit: U128,
+    pub total_deposit: U128,
+    pub can_purchase: bool,
+    pub reason: Option<String>,
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn nft_get_purchase_quote(
+        &self,
+        token_series_id: TokenSeriesId,
+        account_id: AccountId,
+        quantity: Option<u64>,
+    ) -> PurchaseQuote {
+        let token_series = self
+            .token_series_by_id
+            .get(&token_series_id)
+            .expect("Marketplace: Token series not exist");
+        let quantity = quantity.unwrap_or(1);
+        assert!(quantity > 0, "Marketplace: Quantity must be positive");
+
+        let unit_price = token_series.price.unwrap_or(0);
+        let transaction_fee = self
+            .get_series_transaction_fee_at(token_series_id.clone(), to_sec(env::block_timestamp()));
+        let sale_split =
+            self.internal_try_sale_split(&token_series, unit_price, transaction_fee as u128);
+        let reason = match &sale_split {
+            Ok(_) => self.internal_purchase_block_reason(
+                &token_series_id,
+                &token_series,
+                &account_id,
+                quantity,
+            ),
+            Err(reason) => Some(reason.clone()),
+        };
+        // Nothing can be split when the fees don't fit in the price
+        let sale_split = sale_split.unwrap_or_else(|_| SaleSplit {
+            treasury: vec![],
+            venue: None,
+            split: HashMap::new(),
+            seller_id: self.internal_payout_id(&token_series.creator_id),
+            seller_net: 0,
+        });
+
+        let storage_per_token = MINT_STORAGE_OVERHEAD
+            + near_sdk::borsh::to_vec(&token_series.metadata)
+                .unwrap()
+                .len() as StorageUsage
+            + RECEIPT_STORAGE_OVERHEAD
+            + RECEIPT_STORAGE_PER_SPLIT * sale_split.split.len() as StorageUsage;
+        let storage_deposit =
+            storage_per_token as u128 * env::storage_byte_cost().as_yoctonear() * quantity as u128;
+        let total_price = unit_price * quantity as u128;
+
+        PurchaseQuote {
+            token_series_id,
+            account_id,
+            quantity,
+            unit_price: U128(unit_price),
+            transaction_fee,
+            platform_fee: U128(sale_split.treasury.iter().map(|(_, share)| share).sum()),
+            venue_fee: U128(sale_split.venue.map_or(0, |(_, amount)| amount)),
+            split: sale_split
+                .split
+                .into_iter()
+                .map(|(account_id, amount)| (account_id, U128(amount)))
+                .collect(),
+            creator_payout_id: sale_split.seller_id,
+            creator_net: U128(sale_split.seller_net),
+            total_price: U128(total_price),
+            storage_deposit: U128(storage_deposit),
+            total_deposit: U128(total_price + storage_deposit),
+            can_purchase: reason.is_none(),
+            reason,
+        }
+    }
+
+    // Mirrors the checks of nft_buy without panicking
+    fn internal_purchase_block_reason(
+        &self,
+        token_series_id: &TokenSeriesId,
+        token_series: &TokenSeries,
+        account_id: &AccountId,
+        quantity: u64,
+    ) -> Option<String> {
+        if token_series.price.is_none() {
+            return Some("Marketplace: not for sale".to_string());
+        }
+
+        let reserved = self.internal_claim_link_reservations(token_series_id);
+        if let Some(reason) = self.internal_mint_block_reason(token_series, quantity, reserved) {
+            return Some(reason);
+        }
+
+        if let Some(gate) = &token_series.gate {
+            let held = self.internal_gate_held_tokens(gate, account_id);
+            if (held.len() as u32) < gate.count {
+                return Some(format!(
+                    "Marketplace: Must hold {} tokens of series {}",
+                    gate.count, gate.token_series_id
+                ));
+            }
+
+            if gate.one_per_token {
+                let unused = held
+                    .into_iter()
+                    .filter(|token_id| {
+                        !self
+                            .gate_consumed_tokens
+                            .contains(&(token_series_id.clone(), token_id.clone()))
+                    })
+                    .count() as u64;
+                if unused < quantity * gate.count as u64 {
+                    return Some("Marketplace: All gating tokens already used".to_string());
+                }
+            }
+        }
+
+        None
+    }
+
+    // Mirrors the checks of _nft_mint_series without panicking, `reserved` copies
+    // are held back for claim links
+    pub(crate) fn internal_mint_block_reason(
+        &self,
+        token_series: &TokenSeries,
+        quantity: u64,
+        reserved: u64,
+    ) -> Option<String> {
+        if !token_series.is_mintable {
+            return Some("Marketplace: Token series is not mintable".to_string());
+        }
+        if !self.internal_is_active_creator(&token_series.creator_id) {
+            return Some("Marketplace: Creator is no longer whitelisted".to_string());
+        }
+
+        if let Some(event_id) = &token_series.event_id {
+            match self.events_by_id.get(event_id) {
+                None => return Some("Marketplace: Event not exist".to_string()),
+                Some(event)
+                    if !matches!(event.status, EventStatus::Scheduled | EventStatus::Live) =>
+                {
+                    return Some(format!("Marketplace: Event is {:?}", event.status));
+                }
+                _ => {}
+            }
+        }
+
+        let now = to_sec(env::block_timestamp());
+        if token_series
+            .start_time
+            .is_some_and(|start_time| start_time >= now)
+        {
+            return Some("Marketplace: Token series sale not started yet".to_string());
+        }
+        if token_series
+            .end_time
+            .is_some_and(|end_time| end_time <= now)
+        {
+            return Some("Marketplace: Token series sale ended".to_string());
+        }
+
+        if let Some(copies) = token_series.metadata.copies {
+            let remaining = copies.saturating_sub(token_series.tokens.len() + reserved);
+            if remaining == 0 {
+                return Some("Series supply maxed".to_string());
+            }
+            if remaining < quantity {
+                return Some(format!("Marketplace: Only {} copies left", remaining));
+            }
+        }
+
+        if let Some(capacity_pool) = token_series
+            .capacity_pool_id
+            .as_ref()
+            .and_then(|capacity_pool_id| self.capacity_pools.get(capacity_pool_id))
+        {
+            let remaining = capacity_pool.capacity - capacity_pool.minted;
+            if remaining == 0 {
+                return Some("Marketplace: Capacity pool is full".to_string());
+            }
+            if remaining < quantity {
+                return Some(format!(
+                    "Marketplace: Only {} places left in capacity pool",
+                    remaining
+                ));
+            }
+        }
+
+        None
+    }
+}
@@ -0,0 +1,181 @@
+use crate::*;
+use near_sdk::{
+    borsh::{BorshDeserialize, BorshSerialize},
+    near_bindgen,
+    serde::{Deserialize, Serialize},
+    AccountId,
+};
+
+pub type ReceiptId = String;
+
+// Sales are always settled in NEAR
+pub const RECEIPT_CURRENCY: &str = "NEAR";
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub enum SaleType {
+    Primary,
+    Secondary,
+    Renewal,
+    Upgrade,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub struct SaleReceipt {
+    pub receipt_id: ReceiptId,
+    pub token_id: TokenId,
+    pub token_series_id: TokenSeriesId,
+    pub creator_id: AccountId,
+    // Creator payout account on primary sales, previous owner on secondary sales
+    pub seller_id: AccountId,
+    pub buyer_id: AccountId,
+    pub price: U128,
+    pub currency: String,
+    pub platform_fee: U128,
+    pub venue_fee: U128,
+    // Primary split or royalty accounts, without the seller
+    pub royalties: HashMap<AccountId, U128>,
+    pub seller_net: U128,
+    pub sale_type: SaleType,
+    pub timestamp: TimestampSec,
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_receipt(&self, receipt_id: ReceiptId) -> Option<SaleReceipt> {
+        self.receipts_by_id.get(&receipt_id)
+    }
+
+    pub fn get_receipts_by_token(&self, token_id: TokenId) -> Vec<SaleReceipt> {
+        self.internal_paginate_receipts(self.receipts_by_token.get(&token_id), None, None)
+    }
+
+    pub fn get_receipts_by_creator(
+        &self,
+        creator_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<SaleReceipt> {
+        self.internal_paginate_receipts(
+            self.receipts_by_creator.get(&creator_id),
+            from_index,
+            limit,
+        )
+    }
+
+    pub fn get_receipts_by_series(
+        &self,
+        token_series_id: TokenSeriesId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<SaleReceipt> {
+        self.internal_paginate_receipts(
+            self.receipts_by_series.get(&token_series_id),
+            from_index,
+            limit,
+        )
+    }
+
+    fn internal_paginate_receipts(
+        &self,
+        receipt_ids: Option<Vec<ReceiptId>>,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<SaleReceipt> {
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        assert_ne!(limit, 0, "Cannot provide limit of 0.");
+
+        receipt_ids
+            .unwrap_or_default()
+            .iter()
+            .skip(start_index as usize)
+            .take(limit)
+            .map(|receipt_id| self.receipts_by_id.get(receipt_id).unwrap())
+            .collect()
+    }
+
+    // Stores the receipt, indexes it per token, creator and series, updates the
+    // sales counters and logs it. The caller pays for the storage
+    pub(crate) fn internal_add_receipt(
+        &mut self,
+        token_id: &TokenId,
+        token_series: &TokenSeries,
+        buyer_id: AccountId,
+        price: Balance,
+        sale_split: SaleSplit,
+        sale_type: SaleType,
+    ) {
+        self.receipts_nonce += 1;
+        let receipt_id = format!("{}", self.receipts_nonce);
+        let token_series_id = token_id.split(TOKEN_DELIMETER).next().unwrap().to_string();
+
+        let receipt = SaleReceipt {
+            receipt_id,
+            token_id: token_id.clone(),
+            token_series_id,
+            creator_id: token_series.creator_id.clone(),
+            seller_id: sale_split.seller_id,
+            buyer_id,
+            price: U128(price),
+            currency: RECEIPT_CURRENCY.to_string(),
+            platform_fee: U128(sale_split.treasury.iter().map(|(_, share)| share).sum()),
+            venue_fee: U128(sale_split.venue.map_or(0, |(_, amount)| amount)),
+            royalties: sale_split
+                .split
+                .into_iter()
+                .map(|(account_id, amount)| (account_id, U128(amount)))
+                .collect(),
+            seller_net: U128(sale_split.seller_net),
+            sale_type,
+            timestamp: to_sec(env::block_timestamp()),
+        };
+        self.internal_record_sale_stats(&receipt);
+        self.internal_store_receipt(&receipt);
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"nft_sale_receipt",
+            "data": &receipt
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+    }
+
+    // One record per receipt, the indexes only hold receipt ids in sale order
+    fn internal_store_receipt(&mut self, receipt: &SaleReceipt) {
+        let receipt_id = &receipt.receipt_id;
+        self.receipts_by_id.insert(receipt_id, receipt);
+
+        let mut token_receipts = self
+            .receipts_by_token
+            .get(&receipt.token_id)
+            .unwrap_or_default();
+        token_receipts.push(receipt_id.clone());
+        self.receipts_by_token
+            .insert(&receipt.token_id, &token_receipts);
+
+        let mut creator_receipts = self
+            .receipts_by_creator
+            .get(&receipt.creator_id)
+            .unwrap_or_default();
+        creator_receipts.push(receipt_id.clone());
+        self.receipts_by_creator
+            .insert(&receipt.creator_id, &creator_receipts);
+
+        let mut series_receipts = self
+            .receipts_by_series
+            .get(&receipt.token_series_id)
+            .unwrap_or_default();
+        series_receipts.push(receipt_id.clone());
+        self.receipts_by_series
+            .insert(&receipt.token_series_id, &series_receipts);
+    }
+}
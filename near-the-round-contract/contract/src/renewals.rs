@@ -0,0 +1,416 @@
+use crate::*;
+use near_sdk::{
+    borsh::{BorshDeserialize, BorshSerialize},
+    near_bindgen,
+    serde::{Deserialize, Serialize},
+    AccountId,
+};
+
+// Auto renewals open during the last tenth of the renewal period
+const AUTO_RENEW_WINDOW_DIVISOR: TimestampSec = 10;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub struct PassRenewal {
+    pub price: U128,
+    // Seconds added to the expiry by each renewal
+    pub period: TimestampSec,
+    // Seconds after expiry during which the pass can still be renewed
+    pub grace_period: Option<TimestampSec>,
+    // Furthest a renewal can push the expiry past the current time
+    pub max_horizon: Option<TimestampSec>,
+}
+
+// Prepaid balance spent by nft_auto_renew while the depositor holds the token
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub struct RenewalDeposit {
+    pub account_id: AccountId,
+    pub amount: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn nft_set_series_renewal(
+        &mut self,
+        token_series_id: TokenSeriesId,
+        renewal: Option<PassRenewal>,
+    ) {
+        let initial_storage_usage = env::storage_usage();
+
+        let mut token_series = self
+            .token_series_by_id
+            .get(&token_series_id)
+            .expect("Marketplace: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "Marketplace: Creator only"
+        );
+        assert!(
+            matches!(token_series.kind, SeriesKind::VenuePass { .. }),
+            "Marketplace: Only venue passes can be renewed"
+        );
+        if let Some(renewal) = &renewal {
+            assert!(
+                renewal.price.0 < MAX_PRICE,
+                "Marketplace: price higher than {}",
+                MAX_PRICE
+            );
+            assert!(
+                renewal.period > 0,
+                "Marketplace: Renewal period must be positive"
+            );
+            if let Some(max_horizon) = renewal.max_horizon {
+                assert!(
+                    max_horizon >= renewal.period,
+                    "Marketplace: Max horizon is shorter than the renewal period"
+                );
+            }
+        }
+
+        token_series.renewal = renewal.clone();
+        self.token_series_by_id
+            .insert(&token_series_id, &token_series);
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"nft_set_series_renewal",
+            "data": {
+                    "token_series_id": token_series_id,
+                    "renewal": renewal,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+
+        refund_deposit(
+            env::storage_usage().saturating_sub(initial_storage_usage),
+            0,
+        );
+    }
+
+    #[payable]
+    pub fn nft_renew(&mut self, token_id: TokenId) -> TimestampSec {
+        let initial_storage_usage = env::storage_usage();
+        let owner_id = env::predecessor_account_id();
+        assert_eq!(
+            self.tokens.owner_by_id.get(&token_id),
+            Some(owner_id.clone()),
+            "Marketplace: Token owner only"
+        );
+
+        let (token_series_id, token_series, renewal) = self.internal_pass_renewal(&token_id);
+        let price = renewal.price.0;
+        assert!(
+            env::attached_deposit().as_yoctonear() >= price,
+            "Marketplace: attached deposit is less than price : {}",
+            price
+        );
+
+        let valid_until = self.internal_renew(
+            &token_id,
+            &token_series_id,
+            &token_series,
+            &renewal,
+            &owner_id,
+            false,
+        );
+
+        refund_deposit(
+            env::storage_usage().saturating_sub(initial_storage_usage),
+            price,
+        );
+
+        valid_until
+    }
+
+    #[payable]
+    pub fn nft_deposit_renewal(&mut self, token_id: TokenId) -> RenewalDeposit {
+        let account_id = env::predecessor_account_id();
+        assert_eq!(
+            self.tokens.owner_by_id.get(&token_id),
+            Some(account_id.clone()),
+            "Marketplace: Token owner only"
+        );
+        self.internal_pass_renewal(&token_id);
+
+        self.internal_refund_stale_renewal_deposit(&token_id, &account_id);
+        let initial_storage_usage = env::storage_usage();
+
+        let mut renewal_deposit =
+            self.renewal_deposits
+                .get(&token_id)
+                .unwrap_or_else(|| RenewalDeposit {
+                    account_id: account_id.clone(),
+                    amount: U128(0),
+                });
+        // Placeholder so the slot's own storage is included in the measurement
+        self.renewal_deposits.insert(&token_id, &renewal_deposit);
+
+        let storage_cost = env::storage_byte_cost().as_yoctonear()
+            * Balance::from(env::storage_usage() - initial_storage_usage);
+        assert!(
+            env::attached_deposit().as_yoctonear() > storage_cost,
+            "Must attach more than {} yoctoNEAR to cover storage",
+            storage_cost,
+        );
+
+        let amount = env::attached_deposit().as_yoctonear() - storage_cost;
+        renewal_deposit.amount.0 += amount;
+        self.renewal_deposits.insert(&token_id, &renewal_deposit);
+        self.liabilities.renewal_deposits += amount;
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"nft_deposit_renewal",
+            "data": {
+                    "token_id": token_id,
+                    "account_id": account_id,
+                    "amount": U128(amount),
+                    "balance": renewal_deposit.amount,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+
+        renewal_deposit
+    }
+
+    #[payable]
+    pub fn nft_withdraw_renewal_deposit(&mut self, token_id: TokenId) -> U128 {
+        assert_one_yocto();
+
+        let renewal_deposit = self
+            .renewal_deposits
+            .get(&token_id)
+            .expect("Marketplace: No renewal deposit");
+        assert_eq!(
+            env::predecessor_account_id(),
+            renewal_deposit.account_id,
+            "Marketplace: Depositor only"
+        );
+
+        self.internal_refund_renewal_deposit(&token_id);
+
+        renewal_deposit.amount
+    }
+
+    // Anyone can trigger the renewal of a pass close to its expiry, paid from its renewal deposit
+    pub fn nft_auto_renew(&mut self, token_id: TokenId) -> TimestampSec {
+        let initial_storage_usage = env::storage_usage();
+        let owner_id = self
+            .tokens
+            .owner_by_id
+            .get(&token_id)
+            .expect("Marketplace: Token doesn't exist");
+        let (token_series_id, token_series, renewal) = self.internal_pass_renewal(&token_id);
+
+        let valid_until = self
+            .internal_pass_valid_until(&token_id, &token_series)
+            .unwrap();
+        let mut due_at = valid_until.saturating_sub(renewal.period / AUTO_RENEW_WINDOW_DIVISOR);
+        // Waits until the renewed expiry fits in the max horizon
+        if let Some(max_horizon) = renewal.max_horizon {
+            due_at = due_at.max((valid_until + renewal.period).saturating_sub(max_horizon));
+        }
+        assert!(
+            to_sec(env::block_timestamp()) >= due_at,
+            "Marketplace: Pass is not due for renewal until {}",
+            due_at
+        );
+
+        // A previous owner's deposit is refunded and the pass left to expire
+        if self.internal_refund_stale_renewal_deposit(&token_id, &owner_id) {
+            return valid_until;
+        }
+
+        let mut renewal_deposit = self
+            .renewal_deposits
+            .get(&token_id)
+            .expect("Marketplace: No renewal deposit");
+        assert!(
+            renewal_deposit.amount.0 >= renewal.price.0,
+            "Marketplace: Renewal deposit is less than price : {}",
+            renewal.price.0
+        );
+
+        let valid_until = self.internal_renew(
+            &token_id,
+            &token_series_id,
+            &token_series,
+            &renewal,
+            &owner_id,
+            true,
+        );
+
+        // The deposit also pays for the storage of the renewal
+        let storage_cost = env::storage_byte_cost().as_yoctonear()
+            * Balance::from(env::storage_usage().saturating_sub(initial_storage_usage));
+        assert!(
+            renewal_deposit.amount.0 >= renewal.price.0 + storage_cost,
+            "Marketplace: Renewal deposit is less than price and storage : {}",
+            renewal.price.0 + storage_cost
+        );
+
+        renewal_deposit.amount.0 -= renewal.price.0 + storage_cost;
+        self.liabilities.renewal_deposits -= renewal.price.0 + storage_cost;
+        self.renewal_deposits.insert(&token_id, &renewal_deposit);
+
+        valid_until
+    }
+
+    pub fn nft_get_renewal_deposit(&self, token_id: TokenId) -> Option<RenewalDeposit> {
+        self.renewal_deposits.get(&token_id)
+    }
+
+    // Pays the deposit back to whoever made it, along with the storage of its slot
+    pub(crate) fn internal_refund_renewal_deposit(
+        &mut self,
+        token_id: &TokenId,
+    ) -> Option<RenewalDeposit> {
+        let initial_storage_usage = env::storage_usage();
+        let renewal_deposit = self.renewal_deposits.remove(token_id)?;
+        self.liabilities.renewal_deposits -= renewal_deposit.amount.0;
+
+        let storage_refund = env::storage_byte_cost().as_yoctonear()
+            * Balance::from(initial_storage_usage - env::storage_usage());
+        self.internal_pay(
+            &renewal_deposit.account_id,
+            renewal_deposit.amount.0 + storage_refund,
+        );
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"nft_withdraw_renewal_deposit",
+            "data": {
+                    "token_id": token_id,
+                    "account_id": renewal_deposit.account_id,
+                    "amount": renewal_deposit.amount,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+
+        Some(renewal_deposit)
+    }
+
+    // Deposits only renew the pass while their depositor holds it
+    fn internal_refund_stale_renewal_deposit(
+        &mut self,
+        token_id: &TokenId,
+        owner_id: &AccountId,
+    ) -> bool {
+        match self.renewal_deposits.get(token_id) {
+            Some(renewal_deposit) if renewal_deposit.account_id != *owner_id => {
+                self.internal_refund_renewal_deposit(token_id);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn internal_pass_renewal(
+        &self,
+        token_id: &TokenId,
+    ) -> (TokenSeriesId, TokenSeries, PassRenewal) {
+        let token_series_id = token_id.split(TOKEN_DELIMETER).next().unwrap().to_string();
+        let token_series = self
+            .token_series_by_id
+            .get(&token_series_id)
+            .expect("Marketplace: Token series not exist");
+        let renewal = token_series
+            .renewal
+            .clone()
+            .expect("Marketplace: Token series has no renewal");
+
+        (token_series_id, token_series, renewal)
+    }
+
+    // Pushes the expiry forward and splits the renewal price like nft_buy
+    fn internal_renew(
+        &mut self,
+        token_id: &TokenId,
+        token_series_id: &TokenSeriesId,
+        token_series: &TokenSeries,
+        renewal: &PassRenewal,
+        owner_id: &AccountId,
+        auto: bool,
+    ) -> TimestampSec {
+        let now = to_sec(env::block_timestamp());
+        let valid_until = self
+            .internal_pass_valid_until(token_id, token_series)
+            .unwrap();
+
+        let grace_ends_at = valid_until + renewal.grace_period.unwrap_or(0);
+        // The owner already paid for auto renewals, so they go through however late
+        assert!(
+            auto || now <= grace_ends_at,
+            "Marketplace: Pass expired at {}",
+            valid_until
+        );
+
+        // Renewals inside the grace period continue from the previous expiry,
+        // late auto renewals from now
+        let renewed_from = if now > grace_ends_at {
+            now
+        } else {
+            valid_until
+        };
+        let new_valid_until = renewed_from + renewal.period;
+        if let Some(max_horizon) = renewal.max_horizon {
+            assert!(
+                new_valid_until <= now + max_horizon,
+                "Marketplace: Pass cannot be renewed past {}",
+                now + max_horizon
+            );
+        }
+        self.pass_expiries.insert(token_id, &new_valid_until);
+
+        if renewal.price.0 > 0 {
+            self.internal_pay_sale(
+                token_series_id,
+                token_series,
+                token_id,
+                owner_id,
+                renewal.price.0,
+                SaleType::Renewal,
+            );
+        }
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"nft_renew",
+            "data": {
+                    "token_id": token_id,
+                    "owner_id": owner_id,
+                    "price": renewal.price,
+                    "valid_until": new_valid_until,
+                    "auto": auto,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+
+        new_valid_until
+    }
+}
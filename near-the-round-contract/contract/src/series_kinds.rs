@@ -0,0 +1,107 @@
+use crate::*;
+use near_sdk::{
+    borsh::{BorshDeserialize, BorshSerialize},
+    near_bindgen,
+    serde::{Deserialize, Serialize},
+};
+
+#[derive(
+    BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq, Default,
+)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub enum SeriesKind {
+    #[default]
+    Generic,
+    Ticket {
+        event_date: TimestampSec,
+    },
+    VenuePass {
+        // Seconds a pass stays valid after it is minted
+        validity: TimestampSec,
+    },
+    Merchandise {
+        requires_shipping: bool,
+        // Last moment the item can be redeemed, no deadline if None
+        redeem_by: Option<TimestampSec>,
+        // Burn the token on redemption, or keep it as a collectible
+        burn_on_redeem: bool,
+        // Media shown by redeemed collectibles, the original media is kept if None
+        redeemed_media: Option<String>,
+    },
+}
+
+impl SeriesKind {
+    pub(crate) fn assert_valid(&self, start_time: Option<TimestampSec>) {
+        let now = to_sec(env::block_timestamp());
+
+        match self {
+            SeriesKind::Generic => {}
+            SeriesKind::Ticket { event_date } => {
+                assert!(
+                    *event_date > now,
+                    "Marketplace: Ticket event date must be in the future"
+                );
+                if let Some(start_time) = start_time {
+                    assert!(
+                        start_time < *event_date,
+                        "Marketplace: Ticket sale must start before the event date"
+                    );
+                }
+            }
+            SeriesKind::VenuePass { validity } => {
+                assert!(*validity > 0, "Marketplace: Pass validity must be positive");
+            }
+            SeriesKind::Merchandise { redeem_by, .. } => {
+                if let Some(redeem_by) = redeem_by {
+                    assert!(
+                        *redeem_by > now,
+                        "Marketplace: Merchandise redeem_by must be in the future"
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    // End of the validity of a venue pass, None for other kinds
+    pub fn nft_pass_valid_until(&self, token_id: TokenId) -> Option<TimestampSec> {
+        let token_series_id = token_id.split(TOKEN_DELIMETER).next().unwrap();
+        let token_series = self
+            .token_series_by_id
+            .get(&token_series_id.to_string())
+            .expect("Marketplace: Token series not exist");
+
+        self.internal_pass_valid_until(&token_id, &token_series)
+    }
+
+    pub(crate) fn internal_pass_valid_until(
+        &self,
+        token_id: &TokenId,
+        token_series: &TokenSeries,
+    ) -> Option<TimestampSec> {
+        let validity = match token_series.kind {
+            SeriesKind::VenuePass { validity } => validity,
+            _ => return None,
+        };
+
+        // Renewed passes keep their own expiry
+        if let Some(valid_until) = self.pass_expiries.get(token_id) {
+            return Some(valid_until);
+        }
+
+        let issued_at = self
+            .tokens
+            .token_metadata_by_id
+            .as_ref()
+            .and_then(|by_id| by_id.get(token_id))
+            .expect("Marketplace: Token not exist")
+            .issued_at
+            .and_then(|issued_at| issued_at.parse::<Timestamp>().ok())
+            .unwrap_or_default();
+
+        Some(to_sec(issued_at) + validity)
+    }
+}
@@ -0,0 +1,65 @@
+use crate::*;
+use near_sdk::{
+    borsh::{BorshDeserialize, BorshSerialize},
+    near_bindgen,
+    serde::{Deserialize, Serialize},
+};
+
+// Funds held by the contract that belong to someone else
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct Liabilities {
+    pub pending_balances: Balance,
+    // Claim deposits and the unspent key allowances
+    pub claim_link_deposits: Balance,
+    pub renewal_deposits: Balance,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LiabilitiesJson {
+    pub pending_balances: U128,
+    pub claim_link_deposits: U128,
+    pub renewal_deposits: U128,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SolvencyJson {
+    pub account_balance: U128,
+    pub storage_cost: U128,
+    pub liabilities: LiabilitiesJson,
+    pub total_liabilities: U128,
+    pub surplus: U128,
+    pub deficit: U128,
+}
+
+impl Liabilities {
+    pub fn total(&self) -> Balance {
+        self.pending_balances + self.claim_link_deposits + self.renewal_deposits
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_solvency(&self) -> SolvencyJson {
+        let account_balance = env::account_balance().as_yoctonear();
+        let storage_cost =
+            env::storage_byte_cost().as_yoctonear() * Balance::from(env::storage_usage());
+        let available = account_balance.saturating_sub(storage_cost);
+        let total_liabilities = self.liabilities.total();
+
+        SolvencyJson {
+            account_balance: account_balance.into(),
+            storage_cost: storage_cost.into(),
+            liabilities: LiabilitiesJson {
+                pending_balances: self.liabilities.pending_balances.into(),
+                claim_link_deposits: self.liabilities.claim_link_deposits.into(),
+                renewal_deposits: self.liabilities.renewal_deposits.into(),
+            },
+            total_liabilities: total_liabilities.into(),
+            surplus: available.saturating_sub(total_liabilities).into(),
+            deficit: total_liabilities.saturating_sub(available).into(),
+        }
+    }
+}
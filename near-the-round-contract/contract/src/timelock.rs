@@ -0,0 +1,296 @@
+use crate::*;
+use near_sdk::{
+    borsh::{BorshDeserialize, BorshSerialize},
+    json_types::Base64VecU8,
+    near_bindgen,
+    serde::{Deserialize, Serialize},
+    AccountId, Gas,
+};
+
+// Left for the state migration of the deployed code, the deployment itself is cheap
+const GAS_FOR_MIGRATE: Gas = Gas::from_gas(100_000_000_000_000);
+
+pub type TimelockOperationId = String;
+
+// Delay between scheduling a sensitive change and being able to execute it
+pub const TIMELOCK_DELAY_SEC: TimestampSec = 2 * 24 * 60 * 60;
+// Transaction fees above this many basis points have to go through the timelock
+pub const TIMELOCK_FEE_THRESHOLD: u16 = 1_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub enum TimelockAction {
+    // Weighted treasury recipients, set_treasury schedules a one-entry map
+    SetTreasury {
+        treasury: HashMap<AccountId, u32>,
+    },
+    SetTransactionFee {
+        fee: u16,
+    },
+    // Queued behind the fees already scheduled, start_time must be after the delay
+    QueueTransactionFee {
+        fee: u16,
+        start_time: TimestampSec,
+    },
+    // None removes the creator's fee agreement
+    SetCreatorTransactionFee {
+        creator_id: AccountId,
+        fee: Option<u16>,
+    },
+    SetSeriesTransactionFee {
+        token_series_id: TokenSeriesId,
+        fee: u16,
+    },
+    SetSecondaryFee {
+        fee: u16,
+    },
+    AddAdmin {
+        account_id: AccountId,
+    },
+    // Owner only, the proposed owner still has to accept once it executes
+    ProposeOwner {
+        new_owner_id: AccountId,
+    },
+    // sha256 of the wasm passed to execute_timelock_upgrade
+    UpgradeCode {
+        code_hash: Base64VecU8,
+    },
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub struct TimelockOperation {
+    pub operation_id: TimelockOperationId,
+    pub action: TimelockAction,
+    pub proposer_id: AccountId,
+    pub executable_at: TimestampSec,
+}
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn schedule_timelock_operation(&mut self, action: TimelockAction) -> TimelockOperation {
+        let initial_storage_usage = env::storage_usage();
+        self.internal_assert_admin();
+        let executable_at = to_sec(env::block_timestamp()) + TIMELOCK_DELAY_SEC;
+
+        match &action {
+            TimelockAction::SetTreasury { treasury } => {
+                assert!(!treasury.is_empty(), "Marketplace: treasury is empty");
+                assert_eq!(
+                    assert_valid_split(treasury, "treasury"),
+                    10_000,
+                    "Marketplace: treasury weights must sum to 10_000"
+                );
+            }
+            TimelockAction::SetTransactionFee { fee }
+            | TimelockAction::SetSeriesTransactionFee { fee, .. }
+            | TimelockAction::SetSecondaryFee { fee }
+            | TimelockAction::SetCreatorTransactionFee { fee: Some(fee), .. } => {
+                internal_assert_valid_transaction_fee(*fee)
+            }
+            TimelockAction::QueueTransactionFee { fee, start_time } => {
+                internal_assert_valid_transaction_fee(*fee);
+                assert!(
+                    *start_time > executable_at,
+                    "Marketplace: start_time is before the operation is executable at {}",
+                    executable_at
+                );
+            }
+            TimelockAction::ProposeOwner { new_owner_id } => {
+                assert_eq!(
+                    env::predecessor_account_id(),
+                    self.tokens.owner_id,
+                    "Marketplace: Owner only"
+                );
+                assert_ne!(
+                    *new_owner_id, self.tokens.owner_id,
+                    "Marketplace: Already the owner"
+                );
+            }
+            TimelockAction::UpgradeCode { code_hash } => assert_eq!(
+                code_hash.0.len(),
+                32,
+                "Marketplace: code hash must be 32 bytes"
+            ),
+            _ => {}
+        }
+
+        self.timelock_nonce += 1;
+        let operation_id = format!("{}", self.timelock_nonce);
+
+        let operation = TimelockOperation {
+            operation_id: operation_id.clone(),
+            action,
+            proposer_id: env::predecessor_account_id(),
+            executable_at,
+        };
+        self.timelock_operations.insert(&operation_id, &operation);
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"timelock_schedule",
+            "data": &operation
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+
+        refund_deposit(env::storage_usage() - initial_storage_usage, 0);
+
+        operation
+    }
+
+    #[payable]
+    pub fn cancel_timelock_operation(&mut self, operation_id: TimelockOperationId) {
+        assert_one_yocto();
+        self.internal_assert_admin();
+
+        self.timelock_operations
+            .remove(&operation_id)
+            .expect("Marketplace: Timelock operation not exist");
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"timelock_cancel",
+            "data": {
+                    "operation_id": operation_id,
+                    "canceller_id": env::predecessor_account_id(),
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+    }
+
+    pub fn execute_timelock_operation(&mut self, operation_id: TimelockOperationId) {
+        let operation = self.internal_take_timelock_operation(&operation_id);
+
+        match operation.action {
+            TimelockAction::SetTreasury { treasury } => self.treasury = treasury,
+            TimelockAction::SetTransactionFee { fee } => {
+                self.internal_set_transaction_fee(fee, None)
+            }
+            TimelockAction::QueueTransactionFee { fee, start_time } => {
+                self.internal_queue_transaction_fee(fee, start_time)
+            }
+            TimelockAction::SetCreatorTransactionFee { creator_id, fee } => {
+                self.internal_set_creator_transaction_fee(creator_id, fee)
+            }
+            TimelockAction::SetSeriesTransactionFee {
+                token_series_id,
+                fee,
+            } => {
+                self.market_data_transaction_fee
+                    .transaction_fee
+                    .insert(&token_series_id, &u128::from(fee));
+            }
+            TimelockAction::SetSecondaryFee { fee } => self.secondary_fee = fee,
+            TimelockAction::AddAdmin { account_id } => {
+                self.admins.insert(&account_id);
+            }
+            TimelockAction::ProposeOwner { new_owner_id } => {
+                self.internal_propose_owner(new_owner_id)
+            }
+            TimelockAction::UpgradeCode { .. } => {
+                env::panic_str("Marketplace: Use execute_timelock_upgrade for code upgrades")
+            }
+        }
+
+        self.internal_log_timelock_execute(&operation_id);
+    }
+
+    // Takes the raw wasm as input instead of JSON arguments, the new code has to export migrate
+    pub fn execute_timelock_upgrade(&mut self) -> Promise {
+        let code = env::input().expect("Marketplace: Missing code");
+        let code_hash = env::sha256(&code);
+
+        let operation_id = self
+            .timelock_operations
+            .values()
+            .find_map(|operation| match &operation.action {
+                TimelockAction::UpgradeCode {
+                    code_hash: scheduled_hash,
+                } if scheduled_hash.0 == code_hash => Some(operation.operation_id),
+                _ => None,
+            })
+            .expect("Marketplace: Code upgrade not scheduled");
+        self.internal_take_timelock_operation(&operation_id);
+
+        self.internal_log_timelock_execute(&operation_id);
+
+        Promise::new(env::current_account_id())
+            .deploy_contract(code)
+            .function_call(
+                "migrate".to_string(),
+                vec![],
+                NearToken::from_yoctonear(0),
+                GAS_FOR_MIGRATE,
+            )
+    }
+
+    pub fn get_timelock_operation(
+        &self,
+        operation_id: TimelockOperationId,
+    ) -> Option<TimelockOperation> {
+        self.timelock_operations.get(&operation_id)
+    }
+
+    pub fn get_timelock_operations(
+        &self,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<TimelockOperation> {
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        assert_ne!(limit, 0, "Cannot provide limit of 0.");
+
+        self.timelock_operations
+            .values()
+            .skip(start_index as usize)
+            .take(limit)
+            .collect()
+    }
+
+    fn internal_take_timelock_operation(
+        &mut self,
+        operation_id: &TimelockOperationId,
+    ) -> TimelockOperation {
+        let operation = self
+            .timelock_operations
+            .remove(operation_id)
+            .expect("Marketplace: Timelock operation not exist");
+        assert!(
+            to_sec(env::block_timestamp()) >= operation.executable_at,
+            "Marketplace: Timelock operation executable at {}",
+            operation.executable_at
+        );
+
+        operation
+    }
+
+    fn internal_log_timelock_execute(&self, operation_id: &TimelockOperationId) {
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"timelock_execute",
+            "data": {
+                    "operation_id": operation_id,
+                    "executor_id": env::predecessor_account_id(),
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+    }
+}
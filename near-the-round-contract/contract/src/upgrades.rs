@@ -0,0 +1,243 @@
+use crate::*;
+use near_sdk::{
+    borsh::{BorshDeserialize, BorshSerialize},
+    near_bindgen,
+    serde::{Deserialize, Serialize},
+};
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub struct UpgradePath {
+    // Fixed upgrade price, otherwise the difference between the series prices
+    pub price: Option<U128>,
+    // Frees a copy of the old series for sale again instead of only burning the token
+    pub return_to_inventory: bool,
+}
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn nft_set_series_upgrade_path(
+        &mut self,
+        token_series_id: TokenSeriesId,
+        to_token_series_id: TokenSeriesId,
+        upgrade_path: Option<UpgradePath>,
+    ) {
+        let initial_storage_usage = env::storage_usage();
+
+        let mut token_series = self
+            .token_series_by_id
+            .get(&token_series_id)
+            .expect("Marketplace: Token series not exist");
+        let to_token_series = self
+            .token_series_by_id
+            .get(&to_token_series_id)
+            .expect("Marketplace: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "Marketplace: Creator only"
+        );
+        assert_eq!(
+            token_series.creator_id, to_token_series.creator_id,
+            "Marketplace: Upgrade paths must stay within the creator's series"
+        );
+        assert_ne!(
+            token_series_id, to_token_series_id,
+            "Marketplace: Token series cannot upgrade to itself"
+        );
+
+        match &upgrade_path {
+            Some(upgrade_path) => {
+                if let Some(price) = upgrade_path.price {
+                    assert!(
+                        price.0 < MAX_PRICE,
+                        "Marketplace: price higher than {}",
+                        MAX_PRICE
+                    );
+                }
+                token_series
+                    .upgrade_paths
+                    .insert(to_token_series_id.clone(), upgrade_path.clone());
+            }
+            None => {
+                token_series.upgrade_paths.remove(&to_token_series_id);
+            }
+        }
+        self.token_series_by_id
+            .insert(&token_series_id, &token_series);
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"nft_set_series_upgrade_path",
+            "data": {
+                    "token_series_id": token_series_id,
+                    "to_token_series_id": to_token_series_id,
+                    "upgrade_path": upgrade_path,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+
+        refund_deposit(
+            env::storage_usage().saturating_sub(initial_storage_usage),
+            0,
+        );
+    }
+
+    pub fn nft_get_upgrade_price(
+        &self,
+        token_id: TokenId,
+        to_token_series_id: TokenSeriesId,
+    ) -> U128 {
+        let token_series_id = token_id.split(TOKEN_DELIMETER).next().unwrap().to_string();
+        let token_series = self
+            .token_series_by_id
+            .get(&token_series_id)
+            .expect("Marketplace: Token series not exist");
+        let to_token_series = self
+            .token_series_by_id
+            .get(&to_token_series_id)
+            .expect("Marketplace: Token series not exist");
+        let upgrade_path = token_series
+            .upgrade_paths
+            .get(&to_token_series_id)
+            .expect("Marketplace: Upgrade path not allowed");
+
+        U128(self.internal_upgrade_price(&token_id, upgrade_path, &to_token_series))
+    }
+
+    #[payable]
+    pub fn nft_upgrade(&mut self, token_id: TokenId, to_token_series_id: TokenSeriesId) -> Token {
+        let owner_id = env::predecessor_account_id();
+        assert_eq!(
+            self.tokens.owner_by_id.get(&token_id),
+            Some(owner_id.clone()),
+            "Marketplace: Token owner only"
+        );
+
+        // The burned token can't be renewed anymore
+        self.internal_refund_renewal_deposit(&token_id);
+        let initial_storage_usage = env::storage_usage();
+
+        let token_series_id = token_id.split(TOKEN_DELIMETER).next().unwrap().to_string();
+        let mut token_series = self.token_series_by_id.get(&token_series_id).unwrap();
+        let to_token_series = self
+            .token_series_by_id
+            .get(&to_token_series_id)
+            .expect("Marketplace: Token series not exist");
+        let upgrade_path = token_series
+            .upgrade_paths
+            .get(&to_token_series_id)
+            .cloned()
+            .expect("Marketplace: Upgrade path not allowed");
+
+        // Per token state that would otherwise be lost or reused by the upgrade
+        assert!(
+            self.checked_in_tokens.get(&token_id).is_none(),
+            "Marketplace: Checked in tokens cannot be upgraded"
+        );
+
+        let price = self.internal_upgrade_price(&token_id, &upgrade_path, &to_token_series);
+        assert!(
+            env::attached_deposit().as_yoctonear() >= price,
+            "Marketplace: attached deposit is less than price : {}",
+            price
+        );
+
+        // Same gating as buying the new series, the old token still counts as held
+        self.internal_assert_gate(&to_token_series_id, &to_token_series, &owner_id);
+
+        let paid = self.internal_paid_for_token(&token_id);
+        self.internal_burn(&token_id, &owner_id);
+
+        if upgrade_path.return_to_inventory {
+            // The burned copy can be sold again, a sold out or closed series has to be
+            // reopened by its creator
+            if let Some(copies) = token_series.metadata.copies {
+                token_series.metadata.copies = Some(copies + 1);
+            }
+            self.token_series_by_id
+                .insert(&token_series_id, &token_series);
+        }
+
+        // Mint checks the supply and capacity pool of the new series
+        let token = self._nft_mint_series(to_token_series_id.clone(), owner_id.clone());
+
+        // Carried over so later upgrades credit the whole lineage
+        self.upgrade_credits.remove(&token_id);
+        if paid > 0 {
+            self.upgrade_credits.insert(&token.token_id, &paid);
+        }
+
+        if price > 0 {
+            self.internal_pay_sale(
+                &to_token_series_id,
+                &to_token_series,
+                &token.token_id,
+                &owner_id,
+                price,
+                SaleType::Upgrade,
+            );
+        }
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"nft_upgrade",
+            "data": {
+                    "old_token_id": token_id,
+                    "token_id": token.token_id,
+                    "owner_id": owner_id,
+                    "price": U128(price),
+                    "return_to_inventory": upgrade_path.return_to_inventory,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+
+        refund_deposit(
+            env::storage_usage().saturating_sub(initial_storage_usage),
+            price,
+        );
+
+        token
+    }
+
+    fn internal_upgrade_price(
+        &self,
+        token_id: &TokenId,
+        upgrade_path: &UpgradePath,
+        to_token_series: &TokenSeries,
+    ) -> Balance {
+        if let Some(price) = upgrade_path.price {
+            return price.0;
+        }
+
+        to_token_series
+            .price
+            .expect("Marketplace: not for sale")
+            .saturating_sub(self.internal_paid_for_token(token_id))
+    }
+
+    // Purchase and upgrades of the token plus what was paid for the tokens it was
+    // upgraded from, minted or claimed tokens get no credit
+    fn internal_paid_for_token(&self, token_id: &TokenId) -> Balance {
+        let paid: Balance = self
+            .get_receipts_by_token(token_id.clone())
+            .into_iter()
+            .filter(|receipt| matches!(receipt.sale_type, SaleType::Primary | SaleType::Upgrade))
+            .map(|receipt| receipt.price.0)
+            .sum();
+
+        paid + self.upgrade_credits.get(token_id).unwrap_or(0)
+    }
+}
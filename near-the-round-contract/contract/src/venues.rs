@@ -0,0 +1,438 @@
+use crate::*;
+use near_sdk::{
+    assert_one_yocto,
+    borsh::{BorshDeserialize, BorshSerialize},
+    near_bindgen,
+    serde::{Deserialize, Serialize},
+    AccountId,
+};
+
+pub type VenueId = String;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub struct Venue {
+    pub venue_id: VenueId,
+    pub name: String,
+    pub owner_id: AccountId,
+    // Basis points of the sale price paid to the venue owner by nft_buy
+    pub fee: Option<u16>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub struct CheckIn {
+    pub venue_id: VenueId,
+    pub owner_id: AccountId,
+    pub operator_id: AccountId,
+    pub checked_in_at: TimestampSec,
+    // Check-ins of the token so far, including this one
+    pub uses: u32,
+}
+
+// Tokens of series without pass uses can be checked in once
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub struct PassUses {
+    pub max_uses: u32,
+    // Seconds between two check-ins of the same token
+    pub cooldown: Option<TimestampSec>,
+}
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn create_venue(&mut self, name: String, owner_id: AccountId, fee: Option<u16>) -> Venue {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.tokens.owner_id,
+            "Marketplace: Owner only"
+        );
+        internal_assert_valid_venue_fee(fee);
+
+        let initial_storage_usage = env::storage_usage();
+        let venue_id = format!("{}", (self.venues_by_id.len() + 1));
+
+        let venue = Venue {
+            venue_id: venue_id.clone(),
+            name,
+            owner_id,
+            fee,
+        };
+        self.venues_by_id.insert(&venue_id, &venue);
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"venue_create",
+            "data": &venue
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+
+        refund_deposit(env::storage_usage() - initial_storage_usage, 0);
+
+        venue
+    }
+
+    #[payable]
+    pub fn set_venue_fee(&mut self, venue_id: VenueId, fee: Option<u16>) {
+        assert_one_yocto();
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.tokens.owner_id,
+            "Marketplace: Owner only"
+        );
+        internal_assert_valid_venue_fee(fee);
+
+        let mut venue = self
+            .venues_by_id
+            .get(&venue_id)
+            .expect("Marketplace: Venue not exist");
+        venue.fee = fee;
+        self.venues_by_id.insert(&venue_id, &venue);
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"venue_set_fee",
+            "data": {
+                    "venue_id": venue_id,
+                    "fee": fee,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+    }
+
+    #[payable]
+    pub fn add_venue_operator(&mut self, venue_id: VenueId, operator_id: AccountId) {
+        let initial_storage_usage = env::storage_usage();
+
+        self.internal_assert_venue_owner(&venue_id);
+        self.venue_operators
+            .insert(&(venue_id.clone(), operator_id.clone()));
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"venue_add_operator",
+            "data": {
+                    "venue_id": venue_id,
+                    "operator_id": operator_id,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+
+        refund_deposit(env::storage_usage() - initial_storage_usage, 0);
+    }
+
+    #[payable]
+    pub fn remove_venue_operator(&mut self, venue_id: VenueId, operator_id: AccountId) {
+        assert_one_yocto();
+
+        self.internal_assert_venue_owner(&venue_id);
+        self.venue_operators
+            .remove(&(venue_id.clone(), operator_id.clone()));
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"venue_remove_operator",
+            "data": {
+                    "venue_id": venue_id,
+                    "operator_id": operator_id,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+    }
+
+    #[payable]
+    pub fn nft_set_series_venue(
+        &mut self,
+        token_series_id: TokenSeriesId,
+        venue_id: Option<VenueId>,
+    ) {
+        assert_one_yocto();
+
+        let mut token_series = self
+            .token_series_by_id
+            .get(&token_series_id)
+            .expect("Marketplace: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "Marketplace: Creator only"
+        );
+        if let Some(venue_id) = &venue_id {
+            assert!(
+                self.venues_by_id.get(venue_id).is_some(),
+                "Marketplace: Venue not exist"
+            );
+        }
+
+        token_series.venue_id = venue_id.clone();
+        self.token_series_by_id
+            .insert(&token_series_id, &token_series);
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"nft_set_series_venue",
+            "data": {
+                    "token_series_id": token_series_id,
+                    "venue_id": venue_id,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+    }
+
+    // The operator pays for the storage of the check-in record
+    #[payable]
+    pub fn nft_check_in(&mut self, token_id: TokenId) -> CheckIn {
+        let initial_storage_usage = env::storage_usage();
+        let owner_id = self
+            .tokens
+            .owner_by_id
+            .get(&token_id)
+            .expect("Marketplace: Token doesn't exist");
+        let token_series_id: TokenSeriesId =
+            token_id.split(TOKEN_DELIMETER).next().unwrap().to_string();
+        let token_series = self.token_series_by_id.get(&token_series_id).unwrap();
+        let venue_id = token_series
+            .venue_id
+            .clone()
+            .expect("Marketplace: Token series has no venue");
+
+        let operator_id = env::predecessor_account_id();
+        assert!(
+            self.is_venue_operator(venue_id.clone(), operator_id.clone()),
+            "Marketplace: Venue operator only"
+        );
+
+        let now = to_sec(env::block_timestamp());
+        if let Some(valid_until) = self.internal_pass_valid_until(&token_id, &token_series) {
+            assert!(
+                now <= valid_until,
+                "Marketplace: Pass expired at {}",
+                valid_until
+            );
+        }
+
+        let previous_check_in = self.checked_in_tokens.get(&token_id);
+        let uses = previous_check_in
+            .as_ref()
+            .map_or(0, |check_in| check_in.uses);
+
+        match &token_series.pass_uses {
+            None => assert!(
+                previous_check_in.is_none(),
+                "Marketplace: Token already checked in"
+            ),
+            Some(pass_uses) => {
+                assert!(
+                    uses < pass_uses.max_uses,
+                    "Marketplace: Pass has no uses left"
+                );
+                if let (Some(previous_check_in), Some(cooldown)) =
+                    (&previous_check_in, pass_uses.cooldown)
+                {
+                    assert!(
+                        now >= previous_check_in.checked_in_at + cooldown,
+                        "Marketplace: Pass can be used again at {}",
+                        previous_check_in.checked_in_at + cooldown
+                    );
+                }
+            }
+        }
+
+        let check_in = CheckIn {
+            venue_id,
+            owner_id,
+            operator_id,
+            checked_in_at: now,
+            uses: uses + 1,
+        };
+        self.checked_in_tokens.insert(&token_id, &check_in);
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"nft_check_in",
+            "data": {
+                    "token_id": token_id,
+                    "venue_id": check_in.venue_id,
+                    "owner_id": check_in.owner_id,
+                    "operator_id": check_in.operator_id,
+                    "uses": check_in.uses,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+
+        refund_deposit(
+            env::storage_usage().saturating_sub(initial_storage_usage),
+            0,
+        );
+
+        check_in
+    }
+
+    pub fn get_venue(&self, venue_id: VenueId) -> Option<Venue> {
+        self.venues_by_id.get(&venue_id)
+    }
+
+    pub fn get_venues(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<Venue> {
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        let limit = limit.map(|v| v as usize).unwrap_or(usize::MAX);
+        assert_ne!(limit, 0, "Cannot provide limit of 0.");
+
+        self.venues_by_id
+            .values()
+            .skip(start_index as usize)
+            .take(limit)
+            .collect()
+    }
+
+    pub fn is_venue_operator(&self, venue_id: VenueId, account_id: AccountId) -> bool {
+        match self.venues_by_id.get(&venue_id) {
+            Some(venue) => {
+                venue.owner_id == account_id
+                    || self.venue_operators.contains(&(venue_id, account_id))
+            }
+            None => false,
+        }
+    }
+
+    pub fn nft_check_in_status(&self, token_id: TokenId) -> Option<CheckIn> {
+        self.checked_in_tokens.get(&token_id)
+    }
+
+    #[payable]
+    pub fn nft_set_series_pass_uses(
+        &mut self,
+        token_series_id: TokenSeriesId,
+        pass_uses: Option<PassUses>,
+    ) {
+        let initial_storage_usage = env::storage_usage();
+
+        let mut token_series = self
+            .token_series_by_id
+            .get(&token_series_id)
+            .expect("Marketplace: Token series not exist");
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_series.creator_id,
+            "Marketplace: Creator only"
+        );
+        // Holders bought with the uses known at the time
+        assert_eq!(
+            token_series.tokens.len(),
+            0,
+            "Marketplace: Token series already has minted tokens"
+        );
+        if let Some(pass_uses) = &pass_uses {
+            assert!(
+                pass_uses.max_uses > 0,
+                "Marketplace: Max uses must be positive"
+            );
+        }
+
+        token_series.pass_uses = pass_uses.clone();
+        self.token_series_by_id
+            .insert(&token_series_id, &token_series);
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"nft_set_series_pass_uses",
+            "data": {
+                    "token_series_id": token_series_id,
+                    "pass_uses": pass_uses,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+
+        refund_deposit(
+            env::storage_usage().saturating_sub(initial_storage_usage),
+            0,
+        );
+    }
+
+    // Remaining check-ins of a multi-use pass, None for single check-in tokens
+    pub fn nft_remaining_uses(&self, token_id: TokenId) -> Option<u32> {
+        let token_series_id = token_id.split(TOKEN_DELIMETER).next().unwrap().to_string();
+        let pass_uses = self
+            .token_series_by_id
+            .get(&token_series_id)
+            .expect("Marketplace: Token series not exist")
+            .pass_uses?;
+        let uses = self
+            .checked_in_tokens
+            .get(&token_id)
+            .map_or(0, |check_in| check_in.uses);
+
+        Some(pass_uses.max_uses.saturating_sub(uses))
+    }
+
+    fn internal_assert_venue_owner(&self, venue_id: &VenueId) {
+        let venue = self
+            .venues_by_id
+            .get(venue_id)
+            .expect("Marketplace: Venue not exist");
+        assert!(
+            env::predecessor_account_id() == venue.owner_id
+                || env::predecessor_account_id() == self.tokens.owner_id,
+            "Marketplace: Not allowed"
+        );
+    }
+
+    pub(crate) fn internal_venue_fee(
+        &self,
+        token_series: &TokenSeries,
+        price: Balance,
+    ) -> Option<(AccountId, Balance)> {
+        let venue = self.venues_by_id.get(token_series.venue_id.as_ref()?)?;
+        let fee = venue.fee?;
+        Some((venue.owner_id, price * fee as u128 / 10_000u128))
+    }
+}
+
+// Sales pay the venue fee on top of the transaction fee, so both have to fit in the price
+fn internal_assert_valid_venue_fee(fee: Option<u16>) {
+    if let Some(fee) = fee {
+        assert!(
+            u32::from(fee) + u32::from(MAX_TRANSACTION_FEE) <= 10_000,
+            "Marketplace: venue fee is more than {}",
+            10_000 - MAX_TRANSACTION_FEE
+        );
+    }
+}
@@ -0,0 +1,159 @@
+use crate::*;
+use near_sdk::{
+    borsh::{BorshDeserialize, BorshSerialize},
+    json_types::Base64VecU8,
+    near_bindgen,
+    serde::{Deserialize, Serialize},
+    AccountId, CurveType, PublicKey,
+};
+
+// Signed by the series creator over borsh(contract_id, voucher)
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[borsh(crate = "near_sdk::borsh")]
+#[serde(crate = "near_sdk::serde")]
+pub struct MintVoucher {
+    pub token_series_id: TokenSeriesId,
+    pub receiver_id: AccountId,
+    pub price: U128,
+    pub nonce: U64,
+    pub expires_at: TimestampSec,
+}
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn set_voucher_public_key(&mut self, public_key: Option<PublicKey>) {
+        let initial_storage_usage = env::storage_usage();
+        let creator_id = env::predecessor_account_id();
+
+        if let Some(public_key) = &public_key {
+            assert!(
+                matches!(public_key.curve_type(), CurveType::ED25519),
+                "Marketplace: voucher key must be ed25519"
+            );
+            self.voucher_public_keys.insert(&creator_id, public_key);
+        } else {
+            self.voucher_public_keys.remove(&creator_id);
+        }
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"nft_set_voucher_public_key",
+            "data": {
+                    "creator_id": creator_id,
+                    "public_key": public_key,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+
+        refund_deposit(
+            env::storage_usage().saturating_sub(initial_storage_usage),
+            0,
+        );
+        refund_released_storage(
+            &creator_id,
+            initial_storage_usage.saturating_sub(env::storage_usage()),
+        );
+    }
+
+    pub fn get_voucher_public_key(&self, creator_id: AccountId) -> Option<PublicKey> {
+        self.voucher_public_keys.get(&creator_id)
+    }
+
+    pub fn is_voucher_nonce_used(&self, creator_id: AccountId, nonce: U64) -> bool {
+        self.used_voucher_nonces.contains(&(creator_id, nonce.0))
+    }
+
+    #[payable]
+    pub fn nft_redeem_voucher(&mut self, voucher: MintVoucher, signature: Base64VecU8) -> Token {
+        let initial_storage_usage = env::storage_usage();
+        let attached_deposit = env::attached_deposit().as_yoctonear();
+
+        let token_series = self
+            .token_series_by_id
+            .get(&voucher.token_series_id)
+            .expect("Marketplace: Token series not exist");
+
+        assert!(
+            to_sec(env::block_timestamp()) < voucher.expires_at,
+            "Marketplace: Voucher expired"
+        );
+        assert!(
+            !self
+                .used_voucher_nonces
+                .contains(&(token_series.creator_id.clone(), voucher.nonce.0)),
+            "Marketplace: Voucher already redeemed"
+        );
+
+        let public_key = self
+            .voucher_public_keys
+            .get(&token_series.creator_id)
+            .expect("Marketplace: Creator has no voucher key");
+        let message =
+            near_sdk::borsh::to_vec(&(env::current_account_id(), voucher.clone())).unwrap();
+        let signature: [u8; 64] = signature
+            .0
+            .as_slice()
+            .try_into()
+            .expect("Marketplace: Invalid voucher signature");
+        assert!(
+            env::ed25519_verify(
+                &signature,
+                &message,
+                public_key.as_bytes()[1..].try_into().unwrap()
+            ),
+            "Marketplace: Invalid voucher signature"
+        );
+
+        let price = voucher.price.0;
+        assert!(
+            attached_deposit >= price,
+            "Marketplace: attached deposit is less than price : {}",
+            price
+        );
+
+        self.used_voucher_nonces
+            .insert(&(token_series.creator_id.clone(), voucher.nonce.0));
+
+        let token: Token =
+            self._nft_mint_series(voucher.token_series_id.clone(), voucher.receiver_id.clone());
+
+        if price > 0 {
+            self.internal_pay_sale(
+                &voucher.token_series_id,
+                &token_series,
+                &token.token_id,
+                &token.owner_id,
+                price,
+                SaleType::Primary,
+            );
+        }
+
+        let log_data = &json!({
+            "standard":"nep171",
+            "version":"1.0.0",
+            "event":"nft_redeem_voucher",
+            "data": {
+                    "token_series_id": voucher.token_series_id,
+                    "token_id": token.token_id,
+                    "receiver_id": voucher.receiver_id,
+                    "price": voucher.price,
+                    "nonce": voucher.nonce,
+            }
+        })
+        .to_string();
+
+        let event = format!("{}{}", "EVENT_JSON:", log_data);
+
+        env::log_str(&event);
+
+        refund_deposit(env::storage_usage() - initial_storage_usage, price);
+
+        token
+    }
+}
@@ -156,6 +156,11 @@ test("nft buy", async (t) => {
     (await contract.availableBalance()).toHuman()
   );
 
+  const quote: any = await contract.view("nft_get_purchase_quote", {
+    token_series_id: "1",
+    account_id: sam.accountId,
+  });
+
   const result: any = await sam.call(
     contract,
     "nft_buy",
@@ -164,7 +169,7 @@ test("nft buy", async (t) => {
     },
     {
       gas: 100000000000000,
-      attachedDeposit: utils.format.parseNearAmount("1.009"), //1008120000000000000000000
+      attachedDeposit: quote.total_deposit,
     }
   );
 
@@ -217,6 +222,11 @@ test("get nft", async (t) => {
 
   await createSeries(t.context.accounts);
 
+  const quote: any = await contract.view("nft_get_purchase_quote", {
+    token_series_id: "1",
+    account_id: sam.accountId,
+  });
+
   const result: any = await sam.call(
     contract,
     "nft_buy",
@@ -225,7 +235,7 @@ test("get nft", async (t) => {
     },
     {
       gas: 100000000000000,
-      attachedDeposit: utils.format.parseNearAmount("1.009"), //1008120000000000000000000
+      attachedDeposit: quote.total_deposit,
     }
   );
 
@@ -42,7 +42,7 @@ const ListingCard = ({ listing, owned }: ListingCardProps) => {
 
   const buy = async () => {
     if (userData.user?.accountType === "near") {
-      await buyNft(listing.tokenSeriesId, listing.price);
+      await buyNft(listing.tokenSeriesId);
     } else {
       console.log("buy with credit card");
     }
@@ -18,6 +18,8 @@ declare global {
 
 type SmartContractResult = Promise<FinalExecutionOutcome | null | undefined>;
 
+const CONTRACT_ID = "round.testnet";
+
 interface WalletSelectorContextValue {
   selector: WalletSelector;
   modal: WalletSelectorModal;
@@ -36,7 +38,7 @@ interface WalletSelectorContextValue {
     accountId: string,
     shouldWhitelist: boolean
   ) => SmartContractResult;
-  buyNft: (tokenSeriesId: string, price: number) => SmartContractResult;
+  buyNft: (tokenSeriesId: string) => SmartContractResult;
   createSeries: (
     creatorId: string,
     title: string,
@@ -106,12 +108,23 @@ export const WalletSelectorContextProvider = ({ children }: any) => {
     );
   };
 
-  const buyNft = async (tokenSeriesId: string, price: number) => {
-    const priceWithStorage = price + 0.009;
-    const deposit = utils.format.parseNearAmount(priceWithStorage.toString());
-    if (!deposit) throw new Error("Price is not valid.");
+  const buyNft = async (tokenSeriesId: string) => {
+    if (!nearConnection || !accountId) return;
+
+    // The quote covers the price and the storage of the token and its receipt
+    const viewer = await nearConnection.account(accountId);
+    const quote = await viewer.viewFunction(
+      CONTRACT_ID,
+      "nft_get_purchase_quote",
+      { token_series_id: tokenSeriesId, account_id: accountId }
+    );
+    if (!quote.can_purchase) throw new Error(quote.reason);
 
-    return callContract("nft_buy", { token_series_id: tokenSeriesId }, deposit);
+    return callContract(
+      "nft_buy",
+      { token_series_id: tokenSeriesId },
+      quote.total_deposit
+    );
   };
 
   const toggleWhitelistUser = async (
@@ -221,7 +234,7 @@ export const WalletSelectorContextProvider = ({ children }: any) => {
       ],
     });
     const _modal = setupModal(_selector, {
-      contractId: "round.testnet",
+      contractId: CONTRACT_ID,
     });
     const state = _selector.store.getState();
 